@@ -0,0 +1,69 @@
+use std::{
+    num::NonZeroUsize,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use memcached::{Item, MyConfig, ShardedStore};
+
+/// Operations of every client in one iteration, a set for every nine gets
+const OPERATIONS: u64 = 1_000;
+
+fn create_store(shards: usize) -> ShardedStore {
+    let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+    config.memory_limit = 64 * 1024 * 1024;
+    config.shards = Some(shards);
+
+    ShardedStore::new(&config)
+}
+
+/**
+ * Runs `iterations` rounds of operations on a client thread per core, all
+ * of them at once, and returns how long it took.
+ */
+fn run_clients(store: &ShardedStore, clients: usize, iterations: u64) -> Duration {
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for client in 0..clients {
+            scope.spawn(move || {
+                for operation in 0..iterations * OPERATIONS {
+                    let key = format!("key{}:{}", client, operation % 1000);
+                    if operation % 10 == 0 {
+                        let item = Item::new(0, 0, 4, Bytes::from("hola"));
+                        store.lock(&key).insert_or_update(key, item).unwrap();
+                    } else {
+                        store.lock(&key).get_mut(&key);
+                    }
+                }
+            });
+        }
+    });
+
+    start.elapsed()
+}
+
+/**
+ * Throughput of clients setting and getting items on every core, with a
+ * single shard and with one per core.
+ */
+fn scaling_with_the_number_of_shards(c: &mut Criterion) {
+    let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let mut group = c.benchmark_group("clients_on_every_core");
+    group.throughput(Throughput::Elements(cores as u64 * OPERATIONS));
+
+    let mut shard_counts = vec![1, cores];
+    // a single core leaves a single case
+    shard_counts.dedup();
+    for shards in shard_counts {
+        let store = create_store(shards);
+        group.bench_with_input(BenchmarkId::new("shards", shards), &store, |b, store| {
+            b.iter_custom(|iterations| run_clients(store, cores, iterations))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scaling_with_the_number_of_shards);
+criterion_main!(benches);
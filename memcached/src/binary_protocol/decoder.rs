@@ -0,0 +1,213 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+pub const REQUEST_MAGIC: u8 = 0x80;
+pub const HEADER_LENGTH: usize = 24;
+
+/**
+ * Request of the binary protocol: the fields of its 24 bytes header the
+ * server cares about, followed by its body split into extras, key and value.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub opcode: u8,
+    pub opaque: u32,
+    pub cas: u64,
+    pub extras: Bytes,
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The stream is out of sync, nothing else can be read from it
+    InvalidMagic(u8),
+    /// Extras and key do not fit in the body, which has been skipped
+    InvalidLengths { opcode: u8, opaque: u32 },
+    /// The value is bigger than the maximum item size, its body is skipped
+    /// as it is received
+    TooLarge { opcode: u8, opaque: u32 },
+}
+
+/**
+ * Splits the stream of a binary connection in requests. Bodies with a value
+ * bigger than the maximum item size are never buffered: whatever the header
+ * announces, they are discarded as they arrive.
+ */
+pub struct Decoder {
+    max_item_size: usize,
+    /// Bytes of a body too large still to be discarded
+    skipping: usize,
+}
+
+impl Decoder {
+    pub fn new(max_item_size: usize) -> Decoder {
+        Decoder {
+            max_item_size,
+            skipping: 0,
+        }
+    }
+
+    /**
+     * Takes the next request out of `buf`, or returns `None` until all of
+     * its bytes have been received.
+     */
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Request>, DecodeError> {
+        if self.skipping > 0 {
+            let discarded = self.skipping.min(buf.len());
+            buf.advance(discarded);
+            self.skipping -= discarded;
+            if self.skipping > 0 {
+                return Ok(None);
+            }
+        }
+
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        if buf[0] != REQUEST_MAGIC {
+            return Err(DecodeError::InvalidMagic(buf[0]));
+        }
+        if buf.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+
+        let mut header = &buf[..HEADER_LENGTH];
+        header.advance(1);
+        let opcode = header.get_u8();
+        let key_length = header.get_u16() as usize;
+        let extras_length = header.get_u8() as usize;
+        // data type and vbucket id are not used
+        header.advance(3);
+        let body_length = header.get_u32() as usize;
+        let opaque = header.get_u32();
+        let cas = header.get_u64();
+
+        if body_length > extras_length + key_length + self.max_item_size {
+            buf.advance(HEADER_LENGTH);
+            self.skipping = body_length;
+            return Err(DecodeError::TooLarge { opcode, opaque });
+        }
+
+        if buf.len() < HEADER_LENGTH + body_length {
+            buf.reserve(HEADER_LENGTH + body_length - buf.len());
+            return Ok(None);
+        }
+
+        buf.advance(HEADER_LENGTH);
+        let mut body = buf.split_to(body_length).freeze();
+        if extras_length + key_length > body_length {
+            return Err(DecodeError::InvalidLengths { opcode, opaque });
+        }
+
+        Ok(Some(Request {
+            opcode,
+            opaque,
+            cas,
+            extras: body.split_to(extras_length),
+            key: body.split_to(key_length),
+            value: body,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use super::*;
+
+    fn header(opcode: u8, key_length: u16, extras_length: u8, body_length: u32) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.put_u8(REQUEST_MAGIC);
+        buf.put_u8(opcode);
+        buf.put_u16(key_length);
+        buf.put_u8(extras_length);
+        buf.put_u8(0);
+        buf.put_u16(0);
+        buf.put_u32(body_length);
+        buf.put_u32(0xcafe);
+        buf.put_u64(42);
+        buf
+    }
+
+    #[test]
+    fn should_split_the_body_in_extras_key_and_value() {
+        let mut buf = header(0x01, 4, 8, 16);
+        buf.put_u32(3);
+        buf.put_u32(100);
+        buf.put(&b"testhola"[..]);
+        buf.put(&b"next"[..]);
+
+        let request = Decoder::new(1024).decode(&mut buf).unwrap().unwrap();
+        assert_eq!(request.opcode, 0x01);
+        assert_eq!(request.opaque, 0xcafe);
+        assert_eq!(request.cas, 42);
+        assert_eq!(request.extras.len(), 8);
+        assert_eq!(request.key, Bytes::from("test"));
+        assert_eq!(request.value, Bytes::from("hola"));
+        assert_eq!(buf, BytesMut::from(&b"next"[..]));
+    }
+
+    #[test]
+    fn should_wait_until_the_whole_request_is_received() {
+        let mut buf = header(0x00, 4, 0, 4);
+        assert_eq!(
+            Decoder::new(1024).decode(&mut BytesMut::from(&buf[..10])),
+            Ok(None)
+        );
+
+        buf.put(&b"te"[..]);
+        assert_eq!(Decoder::new(1024).decode(&mut buf), Ok(None));
+
+        buf.put(&b"st"[..]);
+        let request = Decoder::new(1024).decode(&mut buf).unwrap().unwrap();
+        assert_eq!(request.key, Bytes::from("test"));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn should_raise_error_on_wrong_magic_or_lengths() {
+        let mut buf = BytesMut::from(&b"get test\r\n"[..]);
+        assert_eq!(
+            Decoder::new(1024).decode(&mut buf),
+            Err(DecodeError::InvalidMagic(b'g'))
+        );
+
+        let mut buf = header(0x00, 8, 0, 4);
+        buf.put(&b"test"[..]);
+        assert_eq!(
+            Decoder::new(1024).decode(&mut buf),
+            Err(DecodeError::InvalidLengths {
+                opcode: 0x00,
+                opaque: 0xcafe
+            })
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn should_skip_bodies_bigger_than_the_max_item_size_without_buffering_them() {
+        let mut decoder = Decoder::new(4);
+        let mut buf = header(0x01, 4, 8, 22);
+        buf.put(&b"extra"[..]);
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Err(DecodeError::TooLarge {
+                opcode: 0x01,
+                opaque: 0xcafe
+            })
+        );
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+        assert!(buf.is_empty());
+
+        buf.put(&b"sxxtest0123456789"[..]);
+        buf.put(header(0x0a, 0, 0, 0));
+        assert_eq!(decoder.decode(&mut buf).unwrap().unwrap().opcode, 0x0a);
+        assert!(buf.is_empty());
+
+        let mut buf = header(0x01, 4, 8, u32::MAX);
+        assert!(decoder.decode(&mut buf).is_err());
+        assert!(buf.capacity() < 1024);
+    }
+}
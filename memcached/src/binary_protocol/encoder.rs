@@ -0,0 +1,135 @@
+use bytes::{BufMut, BytesMut};
+
+use super::decoder::Request;
+
+const RESPONSE_MAGIC: u8 = 0x81;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    NoError = 0x0000,
+    KeyNotFound = 0x0001,
+    KeyExists = 0x0002,
+    ValueTooLarge = 0x0003,
+    InvalidArguments = 0x0004,
+    ItemNotStored = 0x0005,
+    NonNumericValue = 0x0006,
+    UnknownCommand = 0x0081,
+    OutOfMemory = 0x0082,
+}
+
+impl Status {
+    /**
+     * Text sent as the value of error responses.
+     */
+    fn message(&self) -> &'static str {
+        match self {
+            Status::NoError => "",
+            Status::KeyNotFound => "Not found",
+            Status::KeyExists => "Data exists for key.",
+            Status::ValueTooLarge => "Too large.",
+            Status::InvalidArguments => "Invalid arguments",
+            Status::ItemNotStored => "Not stored.",
+            Status::NonNumericValue => "Non-numeric server-side value for incr or decr",
+            Status::UnknownCommand => "Unknown command",
+            Status::OutOfMemory => "Out of memory",
+        }
+    }
+}
+
+/**
+ * Response to a binary request, echoing its opcode and opaque.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response<'a> {
+    pub opcode: u8,
+    pub opaque: u32,
+    pub status: Status,
+    pub cas: u64,
+    pub extras: &'a [u8],
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+}
+
+impl<'a> Response<'a> {
+    pub fn new(request: &Request, status: Status) -> Response<'a> {
+        Response {
+            opcode: request.opcode,
+            opaque: request.opaque,
+            status,
+            cas: 0,
+            extras: &[],
+            key: &[],
+            value: status.message().as_bytes(),
+        }
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) {
+        let body_length = self.extras.len() + self.key.len() + self.value.len();
+        buf.reserve(super::HEADER_LENGTH + body_length);
+
+        buf.put_u8(RESPONSE_MAGIC);
+        buf.put_u8(self.opcode);
+        buf.put_u16(self.key.len() as u16);
+        buf.put_u8(self.extras.len() as u8);
+        // raw bytes data type
+        buf.put_u8(0);
+        buf.put_u16(self.status as u16);
+        buf.put_u32(body_length as u32);
+        buf.put_u32(self.opaque);
+        buf.put_u64(self.cas);
+        buf.put(self.extras);
+        buf.put(self.key);
+        buf.put(self.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn should_encode_header_and_body() {
+        let request = Request {
+            opcode: 0x0c,
+            opaque: 7,
+            cas: 0,
+            extras: Bytes::new(),
+            key: Bytes::from("test"),
+            value: Bytes::new(),
+        };
+        let mut response = Response::new(&request, Status::NoError);
+        response.cas = 42;
+        response.extras = &[0, 0, 0, 3];
+        response.key = b"test";
+        response.value = b"hola";
+
+        let mut buf = BytesMut::new();
+        response.encode(&mut buf);
+
+        let mut expected = vec![0x81, 0x0c, 0, 4, 4, 0, 0, 0, 0, 0, 0, 12, 0, 0, 0, 7];
+        expected.extend_from_slice(&42u64.to_be_bytes());
+        expected.extend_from_slice(&[0, 0, 0, 3]);
+        expected.extend_from_slice(b"testhola");
+        assert_eq!(buf.to_vec(), expected);
+    }
+
+    #[test]
+    fn should_send_the_status_message_as_value_of_errors() {
+        let request = Request {
+            opcode: 0x00,
+            opaque: 0,
+            cas: 0,
+            extras: Bytes::new(),
+            key: Bytes::from("test"),
+            value: Bytes::new(),
+        };
+
+        let mut buf = BytesMut::new();
+        Response::new(&request, Status::KeyNotFound).encode(&mut buf);
+
+        assert_eq!(&buf[6..8], &[0, 1]);
+        assert_eq!(&buf[24..], b"Not found");
+    }
+}
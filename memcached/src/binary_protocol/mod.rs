@@ -0,0 +1,487 @@
+mod decoder;
+mod encoder;
+
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::{
+    commands::{CommandDto, CommandResult, Commands},
+    errors::ParseError,
+    protocol_parser,
+};
+
+pub use self::decoder::{DecodeError, Decoder, Request, HEADER_LENGTH, REQUEST_MAGIC};
+use self::encoder::{Response, Status};
+
+mod opcode {
+    pub const GET: u8 = 0x00;
+    pub const SET: u8 = 0x01;
+    pub const ADD: u8 = 0x02;
+    pub const REPLACE: u8 = 0x03;
+    pub const DELETE: u8 = 0x04;
+    pub const INCREMENT: u8 = 0x05;
+    pub const DECREMENT: u8 = 0x06;
+    pub const QUIT: u8 = 0x07;
+    pub const FLUSH: u8 = 0x08;
+    pub const GETQ: u8 = 0x09;
+    pub const NOOP: u8 = 0x0a;
+    pub const VERSION: u8 = 0x0b;
+    pub const GETK: u8 = 0x0c;
+    pub const GETKQ: u8 = 0x0d;
+    pub const APPEND: u8 = 0x0e;
+    pub const PREPEND: u8 = 0x0f;
+    pub const STAT: u8 = 0x10;
+    pub const SETQ: u8 = 0x11;
+    pub const ADDQ: u8 = 0x12;
+    pub const REPLACEQ: u8 = 0x13;
+    pub const DELETEQ: u8 = 0x14;
+    pub const INCREMENTQ: u8 = 0x15;
+    pub const DECREMENTQ: u8 = 0x16;
+    pub const QUITQ: u8 = 0x17;
+    pub const FLUSHQ: u8 = 0x18;
+    pub const APPENDQ: u8 = 0x19;
+    pub const PREPENDQ: u8 = 0x1a;
+}
+
+/// Expiration of incr and decr asking not to create missing counters
+const NO_INITIAL_VALUE: u32 = 0xffff_ffff;
+
+/**
+ * What to do after running a binary request.
+ */
+#[derive(Debug)]
+pub struct Reply {
+    /// Every response packet to send back, if any
+    pub response: Option<Bytes>,
+    /// Whether the connection has to be closed once the response is sent
+    pub close: bool,
+}
+
+/**
+ * Runs a binary request against the same backend as the text protocol.
+ * Quiet variants only answer when something went wrong, except getq and
+ * getkq which only answer hits.
+ */
+pub fn handle_request(commands: &mut Commands, request: Request) -> Reply {
+    let mut buf = BytesMut::new();
+
+    let result = match request.opcode {
+        opcode::GET | opcode::GETQ | opcode::GETK | opcode::GETKQ => get(commands, &request),
+        opcode::SET | opcode::SETQ | opcode::ADD | opcode::ADDQ => store(commands, &request),
+        opcode::REPLACE | opcode::REPLACEQ => store(commands, &request),
+        opcode::APPEND | opcode::APPENDQ | opcode::PREPEND | opcode::PREPENDQ => {
+            concat(commands, &request)
+        }
+        opcode::DELETE | opcode::DELETEQ => delete(commands, &request),
+        opcode::INCREMENT | opcode::INCREMENTQ | opcode::DECREMENT | opcode::DECREMENTQ => {
+            update_counter(commands, &request)
+        }
+        opcode::FLUSH | opcode::FLUSHQ => flush(commands, &request),
+        opcode::NOOP | opcode::QUIT | opcode::QUITQ => Ok(CommandResult::Ok),
+        opcode::VERSION => Ok(commands.version()),
+        opcode::STAT => stat(commands, &request),
+        _ => Err(Status::UnknownCommand),
+    };
+
+    match result {
+        Ok(result) => respond(&mut buf, &request, &result),
+        Err(status) => Response::new(&request, status).encode(&mut buf),
+    }
+
+    Reply {
+        response: if buf.is_empty() {
+            None
+        } else {
+            Some(buf.freeze())
+        },
+        close: matches!(request.opcode, opcode::QUIT | opcode::QUITQ),
+    }
+}
+
+/**
+ * Answers a request whose body could not be split or was too large, which is
+ * skipped.
+ */
+pub fn invalid_request(error: &DecodeError) -> Reply {
+    let mut buf = BytesMut::new();
+
+    let invalid = match *error {
+        DecodeError::InvalidLengths { opcode, opaque } => {
+            Some((opcode, opaque, Status::InvalidArguments))
+        }
+        DecodeError::TooLarge { opcode, opaque } => Some((opcode, opaque, Status::ValueTooLarge)),
+        DecodeError::InvalidMagic(_) => None,
+    };
+    if let Some((opcode, opaque, status)) = invalid {
+        let request = Request {
+            opcode,
+            opaque,
+            cas: 0,
+            extras: Bytes::new(),
+            key: Bytes::new(),
+            value: Bytes::new(),
+        };
+        Response::new(&request, status).encode(&mut buf);
+    }
+
+    Reply {
+        response: Some(buf.freeze()),
+        close: false,
+    }
+}
+
+type BinaryResult = Result<CommandResult, Status>;
+
+fn get(commands: &mut Commands, request: &Request) -> BinaryResult {
+    if !request.extras.is_empty() || !request.value.is_empty() {
+        return Err(Status::InvalidArguments);
+    }
+
+    Ok(commands.get(&[key(request)?]))
+}
+
+fn store(commands: &mut Commands, request: &Request) -> BinaryResult {
+    if request.extras.len() != 8 {
+        return Err(Status::InvalidArguments);
+    }
+    let mut extras = &request.extras[..];
+    let flags = u16::try_from(extras.get_u32()).map_err(|_| Status::InvalidArguments)?;
+    let exptime = extras.get_u32() as isize;
+
+    let data = CommandDto {
+        key: key(request)?,
+        value: request.value.clone(),
+        flags,
+        exptime,
+        value_size_in_bytes: request.value.len(),
+    };
+
+    // a CAS unique turns set and replace into cas
+    Ok(match request.opcode {
+        opcode::ADD | opcode::ADDQ => commands.add(data),
+        _ if request.cas != 0 => commands.cas(data, request.cas),
+        opcode::SET | opcode::SETQ => commands.set(data),
+        _ => commands.replace(data),
+    })
+}
+
+fn concat(commands: &mut Commands, request: &Request) -> BinaryResult {
+    if !request.extras.is_empty() {
+        return Err(Status::InvalidArguments);
+    }
+
+    let data = CommandDto {
+        key: key(request)?,
+        value: request.value.clone(),
+        flags: 0,
+        exptime: 0,
+        value_size_in_bytes: request.value.len(),
+    };
+
+    Ok(match request.opcode {
+        opcode::APPEND | opcode::APPENDQ => commands.append(data),
+        _ => commands.prepend(data),
+    })
+}
+
+fn delete(commands: &mut Commands, request: &Request) -> BinaryResult {
+    if !request.extras.is_empty() || !request.value.is_empty() {
+        return Err(Status::InvalidArguments);
+    }
+
+    Ok(commands.delete(&key(request)?))
+}
+
+/**
+ * Applies the delta, creating the counter with the initial value when it does
+ * not exist unless the expiration says otherwise.
+ */
+fn update_counter(commands: &mut Commands, request: &Request) -> BinaryResult {
+    if request.extras.len() != 20 || !request.value.is_empty() {
+        return Err(Status::InvalidArguments);
+    }
+    let mut extras = &request.extras[..];
+    let delta = extras.get_u64();
+    let initial = extras.get_u64();
+    let exptime = extras.get_u32();
+    let key = key(request)?;
+
+    let update = |commands: &mut Commands| match request.opcode {
+        opcode::INCREMENT | opcode::INCREMENTQ => commands.incr(&key, delta),
+        _ => commands.decr(&key, delta),
+    };
+
+    let result = update(commands);
+    if result != CommandResult::NotFound || exptime == NO_INITIAL_VALUE {
+        return Ok(result);
+    }
+
+    let value = Bytes::from(initial.to_string());
+    let created = commands.add(CommandDto {
+        key: key.clone(),
+        value_size_in_bytes: value.len(),
+        value,
+        flags: 0,
+        exptime: exptime as isize,
+    });
+
+    Ok(match created {
+        CommandResult::Stored { cas_unique } => CommandResult::Counter {
+            value: initial,
+            cas_unique,
+        },
+        // somebody else created it in the meantime
+        _ => update(commands),
+    })
+}
+
+fn flush(commands: &mut Commands, request: &Request) -> BinaryResult {
+    let delay = match request.extras.len() {
+        0 => 0,
+        4 => (&request.extras[..]).get_u32() as isize,
+        _ => return Err(Status::InvalidArguments),
+    };
+
+    Ok(commands.flush_all(delay))
+}
+
+fn stat(commands: &mut Commands, request: &Request) -> BinaryResult {
+    let group = std::str::from_utf8(&request.key).map_err(|_| Status::InvalidArguments)?;
+
+    Ok(commands.stats(group))
+}
+
+fn key(request: &Request) -> Result<String, Status> {
+    String::from_utf8(request.key.to_vec())
+        .ok()
+        .filter(|key| protocol_parser::is_valid_key(key))
+        .ok_or(Status::InvalidArguments)
+}
+
+fn respond(buf: &mut BytesMut, request: &Request, result: &CommandResult) {
+    let quiet = matches!(
+        request.opcode,
+        opcode::GETQ
+            | opcode::GETKQ
+            | opcode::SETQ
+            | opcode::ADDQ
+            | opcode::REPLACEQ
+            | opcode::DELETEQ
+            | opcode::INCREMENTQ
+            | opcode::DECREMENTQ
+            | opcode::QUITQ
+            | opcode::FLUSHQ
+            | opcode::APPENDQ
+            | opcode::PREPENDQ
+    );
+    let with_key = matches!(request.opcode, opcode::GETK | opcode::GETKQ);
+    let counter;
+    let flags;
+
+    let response = match result {
+        CommandResult::Stored { cas_unique } => {
+            let mut response = Response::new(request, Status::NoError);
+            response.cas = *cas_unique;
+            response
+        }
+        CommandResult::NotStored => {
+            let status = match request.opcode {
+                opcode::ADD | opcode::ADDQ => Status::KeyExists,
+                opcode::REPLACE | opcode::REPLACEQ => Status::KeyNotFound,
+                _ => Status::ItemNotStored,
+            };
+            Response::new(request, status)
+        }
+        CommandResult::Exists => Response::new(request, Status::KeyExists),
+        CommandResult::NotFound => Response::new(request, Status::KeyNotFound),
+        CommandResult::Counter { value, cas_unique } => {
+            counter = value.to_be_bytes();
+            let mut response = Response::new(request, Status::NoError);
+            response.cas = *cas_unique;
+            response.value = &counter;
+            response
+        }
+        CommandResult::NonNumericValue => Response::new(request, Status::NonNumericValue),
+        CommandResult::Values { values, .. } => match values.first() {
+            None if quiet => return,
+            None => {
+                let mut response = Response::new(request, Status::KeyNotFound);
+                if with_key {
+                    response.key = &request.key;
+                }
+                response
+            }
+            Some(value) => {
+                flags = u32::from(value.flags).to_be_bytes();
+                let mut response = Response::new(request, Status::NoError);
+                response.cas = value.cas_unique;
+                response.extras = &flags;
+                if with_key {
+                    response.key = &request.key;
+                }
+                response.value = &value.data;
+                response.encode(buf);
+                return;
+            }
+        },
+        CommandResult::Version(version) => {
+            let mut response = Response::new(request, Status::NoError);
+            response.value = version.as_bytes();
+            response
+        }
+        CommandResult::Stats(stats) => {
+            for (name, value) in stats {
+                let mut response = Response::new(request, Status::NoError);
+                response.key = name.as_bytes();
+                response.value = value.as_bytes();
+                response.encode(buf);
+            }
+            Response::new(request, Status::NoError)
+        }
+        CommandResult::Deleted
+        | CommandResult::Touched
+        | CommandResult::Ok
+        | CommandResult::Reset => Response::new(request, Status::NoError),
+        CommandResult::OutOfMemory => Response::new(request, Status::OutOfMemory),
+        CommandResult::Error(ParseError::ServerError(_)) => {
+            Response::new(request, Status::ValueTooLarge)
+        }
+        // meta commands only exist in the text protocol
+        CommandResult::Meta { .. } | CommandResult::Error(_) => {
+            Response::new(request, Status::UnknownCommand)
+        }
+    };
+
+    if quiet && response.status == Status::NoError {
+        return;
+    }
+    response.encode(buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::BufMut;
+
+    use super::*;
+    use crate::{config::MyConfig, stats::Stats, store_manager::ShardedStore};
+
+    fn create_commands() -> Commands {
+        let config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+
+        Commands::new(
+            Arc::new(ShardedStore::new(&config)),
+            Arc::new(Stats::new()),
+            config,
+        )
+    }
+
+    fn request(opcode: u8, extras: &[u8], key: &str, value: &str) -> Request {
+        Request {
+            opcode,
+            opaque: 7,
+            cas: 0,
+            extras: Bytes::copy_from_slice(extras),
+            key: Bytes::copy_from_slice(key.as_bytes()),
+            value: Bytes::copy_from_slice(value.as_bytes()),
+        }
+    }
+
+    fn set_extras(flags: u32, exptime: u32) -> Vec<u8> {
+        let mut extras = vec![];
+        extras.put_u32(flags);
+        extras.put_u32(exptime);
+        extras
+    }
+
+    fn status(response: &Bytes) -> u16 {
+        u16::from_be_bytes([response[6], response[7]])
+    }
+
+    #[test]
+    fn should_set_and_get_the_value() {
+        let mut commands = create_commands();
+
+        let reply = handle_request(
+            &mut commands,
+            request(opcode::SET, &set_extras(3, 100), "test", "hola"),
+        );
+        let response = reply.response.unwrap();
+        assert_eq!(status(&response), 0);
+        assert_eq!(response.len(), HEADER_LENGTH);
+        assert!(!reply.close);
+
+        let response = handle_request(&mut commands, request(opcode::GETK, &[], "test", ""))
+            .response
+            .unwrap();
+        assert_eq!(status(&response), 0);
+        assert_eq!(&response[HEADER_LENGTH..HEADER_LENGTH + 4], &[0, 0, 0, 3]);
+        assert_eq!(&response[HEADER_LENGTH + 4..], b"testhola");
+    }
+
+    #[test]
+    fn should_only_answer_quiet_requests_when_needed() {
+        let mut commands = create_commands();
+
+        let reply = handle_request(
+            &mut commands,
+            request(opcode::SETQ, &set_extras(0, 100), "test", "hola"),
+        );
+        assert!(reply.response.is_none());
+
+        let reply = handle_request(&mut commands, request(opcode::GETQ, &[], "missing", ""));
+        assert!(reply.response.is_none());
+
+        let reply = handle_request(
+            &mut commands,
+            request(opcode::ADDQ, &set_extras(0, 100), "test", "hola"),
+        );
+        assert_eq!(status(&reply.response.unwrap()), Status::KeyExists as u16);
+    }
+
+    #[test]
+    fn should_create_missing_counters_with_the_initial_value() {
+        let mut commands = create_commands();
+        let mut extras = vec![];
+        extras.put_u64(5);
+        extras.put_u64(10);
+        extras.put_u32(0);
+
+        for expected in [10u64, 15] {
+            let response =
+                handle_request(&mut commands, request(opcode::INCREMENT, &extras, "c", ""))
+                    .response
+                    .unwrap();
+            assert_eq!(status(&response), 0);
+            assert_eq!(&response[HEADER_LENGTH..], &expected.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn should_reject_unknown_opcodes_and_invalid_arguments() {
+        let mut commands = create_commands();
+
+        let response = handle_request(&mut commands, request(0x40, &[], "", ""))
+            .response
+            .unwrap();
+        assert_eq!(status(&response), Status::UnknownCommand as u16);
+
+        let response = handle_request(&mut commands, request(opcode::SET, &[], "test", "hola"))
+            .response
+            .unwrap();
+        assert_eq!(status(&response), Status::InvalidArguments as u16);
+    }
+
+    #[test]
+    fn should_close_the_connection_on_quit() {
+        let mut commands = create_commands();
+
+        let reply = handle_request(&mut commands, request(opcode::QUIT, &[], "", ""));
+        assert!(reply.close);
+        assert!(reply.response.is_some());
+
+        let reply = handle_request(&mut commands, request(opcode::QUITQ, &[], "", ""));
+        assert!(reply.close);
+        assert!(reply.response.is_none());
+    }
+}
@@ -1,141 +1,760 @@
-use crate::{item::Item, types::Store};
+use std::{sync::Arc, time::Duration};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use chrono::Utc;
+
+use crate::{
+    config::{Eviction, MyConfig},
+    errors::ParseError,
+    item::{self, Item},
+    logging,
+    stats::{Counter, Stats},
+    store_manager::{eviction, ClassStats, Cursor, OutOfMemory, ShardedStore, StoreManager},
+    types::Store,
+};
+
+mod meta;
+
+/// Items looked at for every page of lru_crawler metadump
+const METADUMP_PAGE_SIZE: usize = 100;
 
 pub struct Commands {
     store: Store,
+    stats: Arc<Stats>,
+    config: MyConfig,
+}
+
+/**
+ * Outcome of a command, encoded by each protocol in its own way.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandResult {
+    Stored {
+        cas_unique: u64,
+    },
+    NotStored,
+    Exists,
+    NotFound,
+    Deleted,
+    Touched,
+    Counter {
+        value: u64,
+        cas_unique: u64,
+    },
+    NonNumericValue,
+    /// Items found by a retrieval command, in the order requested
+    Values {
+        values: Vec<Value>,
+        with_cas_unique: bool,
+    },
+    Ok,
+    Version(String),
+    Stats(Vec<(String, String)>),
+    Reset,
+    /// No memory left for the item, even after evicting the others of its
+    /// slab class
+    OutOfMemory,
+    /// Response of a meta command: its status code and return flags, plus
+    /// the value when one was asked for
+    Meta {
+        status: &'static str,
+        flags: Vec<String>,
+        value: Option<Bytes>,
+    },
+    Error(ParseError),
 }
 
-type ResultCommand = String;
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub key: String,
+    pub flags: u16,
+    pub cas_unique: u64,
+    pub data: Bytes,
+}
 
 pub struct CommandDto {
     pub(crate) key: String,
-    pub(crate) value: String,
+    pub(crate) value: Bytes,
     pub(crate) flags: u16,
     pub(crate) exptime: isize,
     pub(crate) value_size_in_bytes: usize,
 }
 
 impl Commands {
-    pub fn new(store: Store) -> Commands {
-        Commands { store }
-    }
-
-    pub fn set(&mut self, data: CommandDto) -> ResultCommand {
-        self.store.lock().unwrap().insert(
-            data.key,
-            Item::new(
-                data.flags,
-                data.exptime,
-                data.value_size_in_bytes,
-                data.value,
-            ),
-        );
+    pub fn new(store: Store, stats: Arc<Stats>, config: MyConfig) -> Commands {
+        Commands {
+            store,
+            stats,
+            config,
+        }
+    }
 
-        String::from("STORED\r\n")
+    pub fn set(&mut self, data: CommandDto) -> CommandResult {
+        self.stats.counters.cmd_set.incr();
+        let mut unlocked_store = self.store.lock(&data.key);
+
+        self.live_item(&mut unlocked_store, &data.key);
+        self.insert(&mut unlocked_store, data)
     }
 
-    pub fn get(&mut self, key: &str) -> ResultCommand {
-        return match self.store.lock().unwrap().get(key) {
-            None => String::from("END\r\n"),
-            Some(item) => {
-                if item.expired() {
-                    return String::from("END\r\n");
+    pub fn get(&mut self, keys: &[String]) -> CommandResult {
+        self.retrieve(keys, false, None)
+    }
+
+    pub fn gets(&mut self, keys: &[String]) -> CommandResult {
+        self.retrieve(keys, true, None)
+    }
+
+    pub fn gat(&mut self, exptime: isize, keys: &[String]) -> CommandResult {
+        self.retrieve(keys, false, Some(exptime))
+    }
+
+    pub fn gats(&mut self, exptime: isize, keys: &[String]) -> CommandResult {
+        self.retrieve(keys, true, Some(exptime))
+    }
+
+    /**
+     * Returns every key found, in the order requested. When `exptime` is
+     * given, every item found is touched before being returned.
+     */
+    fn retrieve(
+        &self,
+        keys: &[String],
+        with_cas_unique: bool,
+        exptime: Option<isize>,
+    ) -> CommandResult {
+        let counters = &self.stats.counters;
+        let mut values = vec![];
+
+        for key in keys {
+            let mut unlocked_store = self.store.lock(key);
+            if exptime.is_some() {
+                counters.cmd_touch.incr();
+            } else {
+                counters.cmd_get.incr();
+            }
+            if unlocked_store
+                .get(key)
+                .is_some_and(|item| unlocked_store.is_expired(item))
+            {
+                counters.get_expired.incr();
+            }
+
+            let item = match self.live_item(&mut unlocked_store, key) {
+                Some(item) => item,
+                None if exptime.is_some() => {
+                    counters.touch_misses.incr();
+                    continue;
+                }
+                None => {
+                    counters.get_misses.incr();
+                    continue;
                 }
-                let mut message = format!("VALUE {} {} {}\r\n", key, item.flags, item.value_length);
-                message += &item.value;
-                message += "\r\nEND\r\n";
+            };
+            if let Some(exptime) = exptime {
+                counters.touch_hits.incr();
+                item.touch(exptime);
+            } else {
+                counters.get_hits.incr();
+            }
+            item.fetch();
+
+            values.push(Value {
+                key: key.clone(),
+                flags: item.flags,
+                cas_unique: item.cas_unique,
+                data: item.value.clone(),
+            });
+        }
+
+        CommandResult::Values {
+            values,
+            with_cas_unique,
+        }
+    }
+
+    pub fn add(&mut self, data: CommandDto) -> CommandResult {
+        self.add_comparing(data, None)
+    }
 
-                message
+    /**
+     * add answering EXISTS rather than NOT_STORED when the item found has a
+     * CAS unique other than `compare_cas`.
+     */
+    fn add_comparing(&mut self, data: CommandDto, compare_cas: Option<u64>) -> CommandResult {
+        self.stats.counters.cmd_set.incr();
+        let mut unlocked_store = self.store.lock(&data.key);
+
+        match self.live_item(&mut unlocked_store, &data.key) {
+            Some(item) if compare_cas.is_some_and(|cas_unique| cas_unique != item.cas_unique) => {
+                self.stats.counters.cas_badval.incr();
+                return CommandResult::Exists;
             }
+            Some(_) => return CommandResult::NotStored,
+            None => {}
+        }
+
+        self.insert(&mut unlocked_store, data)
+    }
+
+    pub fn replace(&mut self, data: CommandDto) -> CommandResult {
+        self.stats.counters.cmd_set.incr();
+        let mut unlocked_store = self.store.lock(&data.key);
+
+        if self.live_item(&mut unlocked_store, &data.key).is_none() {
+            return CommandResult::NotStored;
+        }
+
+        self.insert(&mut unlocked_store, data)
+    }
+
+    pub fn append(&mut self, data: CommandDto) -> CommandResult {
+        self.concat(data, false, None)
+    }
+
+    pub fn prepend(&mut self, data: CommandDto) -> CommandResult {
+        self.concat(data, true, None)
+    }
+
+    /**
+     * append or prepend, only to an item whose CAS unique is `compare_cas`
+     * when given.
+     */
+    fn concat(
+        &mut self,
+        data: CommandDto,
+        prepend: bool,
+        compare_cas: Option<u64>,
+    ) -> CommandResult {
+        self.stats.counters.cmd_set.incr();
+        let mut unlocked_store = self.store.lock(&data.key);
+
+        let mut item = match self.live_item(&mut unlocked_store, &data.key) {
+            None => return CommandResult::NotStored,
+            Some(item) if compare_cas.is_some_and(|cas_unique| cas_unique != item.cas_unique) => {
+                self.stats.counters.cas_badval.incr();
+                return CommandResult::Exists;
+            }
+            Some(item) => item.clone(),
+        };
+        if item.size(&data.key) + data.value.len() > self.config.max_item_size {
+            return CommandResult::Error(ParseError::ServerError("out of memory storing object"));
+        }
+
+        let (first, second) = if prepend {
+            (&data.value, &item.value)
+        } else {
+            (&item.value, &data.value)
         };
+        let mut value = BytesMut::with_capacity(first.len() + second.len());
+        value.put(first.as_ref());
+        value.put(second.as_ref());
+        item.update_value(value.freeze());
+        let cas_unique = item.cas_unique;
+        // stored again for the store to account for its new size
+        if self
+            .store_item(&mut unlocked_store, data.key, item)
+            .is_err()
+        {
+            return CommandResult::OutOfMemory;
+        }
+        self.stats.counters.total_items.incr();
+
+        CommandResult::Stored { cas_unique }
     }
 
-    pub fn add(&mut self, data: CommandDto) -> ResultCommand {
-        let mut unlocked_store = self.store.lock().unwrap();
+    /**
+     * Stores the value only if nobody else has updated the item since the
+     * client fetched `cas_unique` with gets.
+     */
+    pub fn cas(&mut self, data: CommandDto, cas_unique: u64) -> CommandResult {
+        let counters = &self.stats.counters;
+        counters.cmd_set.incr();
+        let mut unlocked_store = self.store.lock(&data.key);
 
-        match unlocked_store.get(&data.key) {
+        match self.live_item(&mut unlocked_store, &data.key) {
             None => {
-                unlocked_store.insert(
-                    data.key,
-                    Item::new(
-                        data.flags,
-                        data.exptime,
-                        data.value_size_in_bytes,
-                        data.value,
-                    ),
-                );
-
-                String::from("STORED\r\n")
+                counters.cas_misses.incr();
+                CommandResult::NotFound
+            }
+            Some(item) if item.cas_unique != cas_unique => {
+                counters.cas_badval.incr();
+                CommandResult::Exists
             }
+            Some(_) => {
+                counters.cas_hits.incr();
+                self.insert(&mut unlocked_store, data)
+            }
+        }
+    }
+
+    pub fn delete(&mut self, key: &str) -> CommandResult {
+        let mut unlocked_store = self.store.lock(key);
+
+        if self.live_item(&mut unlocked_store, key).is_none() {
+            self.stats.counters.delete_misses.incr();
+            return CommandResult::NotFound;
+        }
+
+        unlocked_store.remove(key);
+        self.stats.counters.delete_hits.incr();
+        CommandResult::Deleted
+    }
+
+    pub fn touch(&mut self, key: &str, exptime: isize) -> CommandResult {
+        self.stats.counters.cmd_touch.incr();
+        let mut unlocked_store = self.store.lock(key);
+
+        match self.live_item(&mut unlocked_store, key) {
             Some(item) => {
-                if item.expired() {
-                    unlocked_store.insert(
-                        data.key,
-                        Item::new(
-                            data.flags,
-                            data.exptime,
-                            data.value_size_in_bytes,
-                            data.value,
-                        ),
-                    );
+                item.touch(exptime);
+                self.stats.counters.touch_hits.incr();
+                CommandResult::Touched
+            }
+            None => {
+                self.stats.counters.touch_misses.incr();
+                CommandResult::NotFound
+            }
+        }
+    }
 
-                    String::from("STORED\r\n")
-                } else {
-                    String::from("NOT_STORED\r\n")
-                }
+    pub fn incr(&mut self, key: &str, delta: u64) -> CommandResult {
+        self.change_counter(&mut self.store.lock(key), key, delta, false)
+    }
+
+    pub fn decr(&mut self, key: &str, delta: u64) -> CommandResult {
+        self.change_counter(&mut self.store.lock(key), key, delta, true)
+    }
+
+    /**
+     * incr, or decr when `decrement`, in the shard of `key` already locked.
+     */
+    fn change_counter(
+        &self,
+        store: &mut StoreManager,
+        key: &str,
+        delta: u64,
+        decrement: bool,
+    ) -> CommandResult {
+        let counters = &self.stats.counters;
+        if decrement {
+            // counters never go below zero
+            self.update_counter(
+                store,
+                key,
+                |counter| counter.saturating_sub(delta),
+                [&counters.decr_hits, &counters.decr_misses],
+            )
+        } else {
+            // 64 bits counters wrap around on overflow
+            self.update_counter(
+                store,
+                key,
+                |counter| counter.wrapping_add(delta),
+                [&counters.incr_hits, &counters.incr_misses],
+            )
+        }
+    }
+
+    fn update_counter(
+        &self,
+        store: &mut StoreManager,
+        key: &str,
+        operation: impl FnOnce(u64) -> u64,
+        [hits, misses]: [&Counter; 2],
+    ) -> CommandResult {
+        let mut item = match self.live_item(store, key) {
+            Some(item) => item.clone(),
+            None => {
+                misses.incr();
+                return CommandResult::NotFound;
             }
+        };
+        hits.incr();
+
+        let counter = match parse_counter(&item.value) {
+            None => return CommandResult::NonNumericValue,
+            Some(counter) => counter,
+        };
+
+        let counter = operation(counter);
+        item.update_value(Bytes::from(counter.to_string()));
+        let cas_unique = item.cas_unique;
+        if self.store_item(store, key.to_owned(), item).is_err() {
+            return CommandResult::OutOfMemory;
+        }
+
+        CommandResult::Counter {
+            value: counter,
+            cas_unique,
         }
     }
 
-    pub fn replace(&mut self, data: CommandDto) -> ResultCommand {
-        let mut unlocked_store = self.store.lock().unwrap();
+    /**
+     * Invalidates every item present once `delay` is over, read the way an
+     * exptime is: seconds from now up to 30 days, a Unix time beyond. Items
+     * are invalidated right away without a delay or with one already past. A
+     * later flush_all replaces a pending one.
+     */
+    pub fn flush_all(&mut self, delay: isize) -> CommandResult {
+        self.stats.counters.cmd_flush.incr();
+        let generation = self.store.new_flush_generation();
 
-        match unlocked_store.get(&data.key) {
-            None => String::from("NOT_STORED\r\n"),
-            Some(_) => {
-                unlocked_store.insert(
-                    data.key,
-                    Item::new(
-                        data.flags,
-                        data.exptime,
-                        data.value_size_in_bytes,
-                        data.value,
-                    ),
+        let seconds = item::expiration_timestamp(delay).map_or(0, |at| at - Utc::now().timestamp());
+        if seconds <= 0 {
+            self.store.flush_all();
+        } else {
+            let store = self.store.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(seconds as u64)).await;
+                if store.flush_generation() == generation {
+                    store.flush_all();
+                }
+            });
+        }
+
+        CommandResult::Ok
+    }
+
+    pub fn version(&self) -> CommandResult {
+        CommandResult::Version(env!("CARGO_PKG_VERSION").to_owned())
+    }
+
+    pub fn verbosity(&mut self, verbosity: u32) -> CommandResult {
+        logging::set_verbosity(verbosity);
+
+        CommandResult::Ok
+    }
+
+    /**
+     * Answers `stats` and its sub-commands: general counters when `group` is
+     * empty, otherwise "settings", "items", "slabs", "conns" or "reset".
+     */
+    pub fn stats(&mut self, group: &str) -> CommandResult {
+        let mut stats = vec![];
+
+        match group {
+            "" => {
+                stat(&mut stats, "pid", std::process::id());
+                stat(&mut stats, "uptime", self.stats.uptime());
+                stat(&mut stats, "time", Utc::now().timestamp());
+                stat(&mut stats, "version", env!("CARGO_PKG_VERSION"));
+                stat(&mut stats, "pointer_size", usize::BITS);
+                stat(
+                    &mut stats,
+                    "curr_connections",
+                    self.stats.curr_connections(),
+                );
+                for (name, value) in self.stats.counters.values() {
+                    stat(&mut stats, name, value);
+                }
+                // items in the store, expired ones included until they are
+                // reclaimed
+                stat(&mut stats, "curr_items", self.store.len());
+                stat(&mut stats, "bytes", self.store.bytes());
+                stat(&mut stats, "limit_maxbytes", self.store.max_bytes());
+                stat(&mut stats, "lru_maintainer_juggles", self.store.juggles());
+                stat(&mut stats, "slabs_moved", self.store.slabs_moved());
+            }
+            "settings" => {
+                stat(&mut stats, "maxbytes", self.config.memory_limit);
+                stat(&mut stats, "tcpport", self.config.port);
+                stat(&mut stats, "cas_enabled", "yes");
+                stat(&mut stats, "flush_enabled", "yes");
+                stat(&mut stats, "binding_protocol", "auto-negotiate");
+                stat(&mut stats, "item_size_max", self.config.max_item_size);
+                stat(&mut stats, "growth_factor", self.config.growth_factor);
+                stat(&mut stats, "chunk_size", self.config.min_chunk_size);
+                stat(
+                    &mut stats,
+                    "eviction_policy",
+                    self.config.eviction_policy.name(),
                 );
+                stat(&mut stats, "lru_maintainer_thread", "yes");
+                stat(&mut stats, "lru_crawler", "yes");
+                let segmented = self.config.eviction_policy == Eviction::Lru;
+                stat(
+                    &mut stats,
+                    "lru_segmented",
+                    if segmented { "yes" } else { "no" },
+                );
+                stat(&mut stats, "hot_lru_pct", eviction::HOT_LRU_PCT);
+                stat(&mut stats, "warm_lru_pct", eviction::WARM_LRU_PCT);
+                stat(&mut stats, "temporary_ttl", eviction::TEMPORARY_TTL);
+                stat(&mut stats, "shards", self.store.shard_count());
+            }
+            "items" => {
+                for (id, class) in slab_classes(&self.store) {
+                    if class.used_chunks == 0 {
+                        continue;
+                    }
+                    let prefix = format!("items:{}", id);
+                    stat(&mut stats, &format!("{}:number", prefix), class.used_chunks);
+                    stat(&mut stats, &format!("{}:evicted", prefix), class.evicted);
+                    stat(
+                        &mut stats,
+                        &format!("{}:reclaimed", prefix),
+                        class.reclaimed,
+                    );
+                    stat(
+                        &mut stats,
+                        &format!("{}:expired_unfetched", prefix),
+                        class.expired_unfetched,
+                    );
+                    for (name, value) in &class.policy {
+                        stat(&mut stats, &format!("{}:{}", prefix, name), value);
+                    }
+                }
+            }
+            "slabs" => {
+                let mut active_slabs = 0;
+                for (id, class) in slab_classes(&self.store) {
+                    if class.pages == 0 {
+                        continue;
+                    }
+                    active_slabs += 1;
+                    stat(&mut stats, &format!("{}:chunk_size", id), class.chunk_size);
+                    stat(
+                        &mut stats,
+                        &format!("{}:chunks_per_page", id),
+                        class.chunks_per_page,
+                    );
+                    stat(&mut stats, &format!("{}:total_pages", id), class.pages);
+                    stat(
+                        &mut stats,
+                        &format!("{}:total_chunks", id),
+                        class.total_chunks,
+                    );
+                    stat(
+                        &mut stats,
+                        &format!("{}:used_chunks", id),
+                        class.used_chunks,
+                    );
+                    stat(
+                        &mut stats,
+                        &format!("{}:free_chunks", id),
+                        class.total_chunks - class.used_chunks,
+                    );
+                }
+                stat(&mut stats, "active_slabs", active_slabs);
+                stat(&mut stats, "total_malloced", self.store.total_malloced());
+            }
+            "conns" => {
+                for connection in self.stats.connections() {
+                    let id = connection.id;
+                    stat(
+                        &mut stats,
+                        &format!("{}:addr", id),
+                        format!("tcp:{}", connection.addr),
+                    );
+                    stat(
+                        &mut stats,
+                        &format!("{}:secs_since_last_cmd", id),
+                        connection.secs_since_last_cmd,
+                    );
+                }
+            }
+            "reset" => {
+                self.stats.reset();
+                return CommandResult::Reset;
+            }
+            _ => return CommandResult::Error(ParseError::UnknownCommand),
+        }
+
+        CommandResult::Stats(stats)
+    }
+
+    fn insert(&self, store: &mut StoreManager, data: CommandDto) -> CommandResult {
+        let item = Item::new(
+            data.flags,
+            data.exptime,
+            data.value_size_in_bytes,
+            data.value,
+        );
+        // the whole item, header included, has to fit in a slab page
+        if item.size(&data.key) > self.config.max_item_size {
+            return CommandResult::Error(ParseError::ServerError("object too large for cache"));
+        }
+        let cas_unique = item.cas_unique;
+        if self.store_item(store, data.key, item).is_err() {
+            return CommandResult::OutOfMemory;
+        }
+        self.stats.counters.total_items.incr();
 
-                String::from("STORED\r\n")
+        CommandResult::Stored { cas_unique }
+    }
+
+    /**
+     * Adds the item to the store, counting the eviction it may cause unless
+     * the item evicted had already expired.
+     */
+    fn store_item(
+        &self,
+        store: &mut StoreManager,
+        key: String,
+        item: Item,
+    ) -> Result<(), OutOfMemory> {
+        for (key, evicted) in store.insert_or_update(key, item)? {
+            if !store.is_expired(&evicted) {
+                tracing::debug!("evicted {}", key);
+                self.stats.counters.evictions.incr();
             }
         }
+
+        Ok(())
     }
 
-    pub fn append(&mut self, data: CommandDto) -> ResultCommand {
-        let mut unlocked_store = self.store.lock().unwrap();
+    /**
+     * A page of lru_crawler metadump: a line for every live item of slab
+     * class `class`, or of any class, from `cursor` on, and the cursor of the
+     * next page, `None` after the last one.
+     */
+    pub fn metadump(
+        &self,
+        cursor: Cursor,
+        class: Option<u64>,
+    ) -> Result<(Vec<String>, Option<Cursor>), ParseError> {
+        let classes = self.store.lock_shard(0).slab_classes().len() as u64;
+        if class.is_some_and(|class| class == 0 || class > classes) {
+            return Err(ParseError::BadCommandLineFormat);
+        }
 
-        match unlocked_store.get(&data.key) {
-            None => String::from("NOT_STORED\r\n"),
-            Some(_) => {
-                unlocked_store.entry(data.key).and_modify(|val| {
-                    val.value = val.value.to_owned() + data.value.trim_end();
-                    val.value_length = val.value.len();
-                });
+        let (items, next) = self.store.scan(cursor, METADUMP_PAGE_SIZE);
+        let lines = items
+            .into_iter()
+            .filter(|scanned| class.is_none_or(|class| scanned.class_id as u64 == class))
+            .map(|scanned| {
+                let item = &scanned.item;
+                format!(
+                    "key={} exp={} la={} cas={} fetch={} size={}",
+                    urlencode(&scanned.key),
+                    item.exptime().unwrap_or(-1),
+                    item.last_accessed_at(),
+                    item.cas_unique,
+                    if item.fetched { "yes" } else { "no" },
+                    item.size(&scanned.key)
+                )
+            })
+            .collect();
 
-                String::from("STORED\r\n")
+        Ok((lines, next))
+    }
+
+    /**
+     * Returns the item stored for `key` unless it expired, in which case it is
+     * removed from the store and treated as absent.
+     */
+    fn live_item<'a>(&self, store: &'a mut StoreManager, key: &str) -> Option<&'a mut Item> {
+        if store.get(key).is_some_and(|item| store.is_expired(item)) {
+            let item = store.reclaim(key).unwrap();
+            self.stats.counters.reclaimed.incr();
+            if !item.fetched {
+                self.stats.counters.expired_unfetched.incr();
             }
         }
+
+        store.get_mut(key)
     }
+}
 
-    pub fn prepend(&mut self, data: CommandDto) -> ResultCommand {
-        let mut unlocked_store = self.store.lock().unwrap();
+/**
+ * Percent-encodes every byte of the key but unreserved URI characters, as
+ * memcached does in metadump lines. Keys hold no whitespace nor control
+ * characters, but may hold `%` or bytes beyond ASCII, which tools decoding
+ * the lines expect encoded.
+ */
+fn urlencode(key: &str) -> String {
+    key.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
 
-        match unlocked_store.get(&data.key) {
-            None => String::from("NOT_STORED\r\n"),
-            Some(_) => {
-                unlocked_store.entry(data.key).and_modify(|val| {
-                    val.value = data.value.trim_end().to_owned() + &val.value;
-                    val.value_length = val.value.len();
-                });
+fn stat(stats: &mut Vec<(String, String)>, name: &str, value: impl ToString) {
+    stats.push((name.to_owned(), value.to_string()));
+}
+
+/**
+ * Slab classes of every shard along with their ids, which start at 1.
+ */
+fn slab_classes(store: &ShardedStore) -> impl Iterator<Item = (usize, ClassStats)> {
+    store
+        .class_stats()
+        .into_iter()
+        .enumerate()
+        .map(|(index, class)| (index + 1, class))
+}
+
+/**
+ * Reads a value stored as a decimal representation of a 64 bits unsigned
+ * integer, as incr and decr expect.
+ */
+fn parse_counter(value: &[u8]) -> Option<u64> {
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                String::from("STORED\r\n")
+    /**
+     * Commands on a store of `pages` pages of 128 bytes, the first slab class
+     * holding one 72 bytes chunk per page.
+     */
+    fn create_commands(pages: usize) -> Commands {
+        let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+        config.max_item_size = 128;
+        config.min_chunk_size = 16;
+        config.memory_limit = pages * config.max_item_size;
+
+        Commands::new(
+            Arc::new(ShardedStore::new(&config)),
+            Arc::new(Stats::new()),
+            config,
+        )
+    }
+
+    fn set(commands: &mut Commands, key: &str) {
+        let value = Bytes::from("hola");
+        commands.set(CommandDto {
+            key: key.to_owned(),
+            value_size_in_bytes: value.len(),
+            value,
+            flags: 0,
+            exptime: 0,
+        });
+    }
+
+    fn keys(commands: &mut Commands, keys: &[&str]) -> Vec<String> {
+        let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        match commands.get(&keys) {
+            CommandResult::Values { values, .. } => {
+                values.into_iter().map(|value| value.key).collect()
             }
+            result => panic!("unexpected result {:?}", result),
         }
     }
+
+    #[test]
+    fn should_evict_the_least_recently_used_item_when_full() {
+        // room for two items with 1 byte keys and 4 bytes values
+        let mut commands = create_commands(2);
+        set(&mut commands, "a");
+        set(&mut commands, "b");
+
+        // reading "a" makes "b" the least recently used item
+        assert_eq!(keys(&mut commands, &["a"]), ["a"]);
+        set(&mut commands, "c");
+
+        assert_eq!(keys(&mut commands, &["a", "b", "c"]), ["a", "c"]);
+        assert_eq!(commands.stats.counters.evictions.get(), 1);
+    }
 }
@@ -0,0 +1,330 @@
+use bytes::Bytes;
+
+use super::{parse_counter, CommandDto, CommandResult, Commands};
+use crate::{item::Item, protocol_parser::MetaFlags};
+
+impl Commands {
+    /**
+     * mg: returns whichever parts of the item the flags ask for. A stale
+     * item is still served, flagged X, and the first client to see it gets
+     * the W flag telling it to recache the item while the rest get Z.
+     */
+    pub fn meta_get(&mut self, key: &str, flags: &MetaFlags) -> CommandResult {
+        let counters = &self.stats.counters;
+        counters.cmd_get.incr();
+        if flags.ttl.is_some() {
+            counters.cmd_touch.incr();
+        }
+        let mut unlocked_store = self.store.lock(key);
+
+        let mut won = false;
+        if self.live_item(&mut unlocked_store, key).is_none() {
+            let exptime = match flags.vivify {
+                None => {
+                    counters.get_misses.incr();
+                    return meta("EN", vec![], None);
+                }
+                Some(exptime) => exptime,
+            };
+            // the first client missing the item is the one to fill it
+            counters.get_misses.incr();
+            let mut item = Item::new(0, exptime, 0, Bytes::new());
+            item.win_token_sent = true;
+            if self
+                .store_item(&mut unlocked_store, key.to_owned(), item)
+                .is_err()
+            {
+                return CommandResult::OutOfMemory;
+            }
+            won = true;
+        } else {
+            counters.get_hits.incr();
+        }
+
+        let item = unlocked_store.get_mut(key).unwrap();
+        if let Some(exptime) = flags.ttl {
+            item.touch(exptime);
+            counters.touch_hits.incr();
+        }
+
+        let mut returned = item_flags(key, item, flags);
+        if item.stale {
+            returned.push("X".to_owned());
+        }
+        if won || (item.stale && !item.win_token_sent) {
+            item.win_token_sent = true;
+            returned.push("W".to_owned());
+        } else if item.win_token_sent {
+            returned.push("Z".to_owned());
+        }
+        item.fetch();
+
+        if flags.return_value {
+            meta("VA", returned, Some(item.value.clone()))
+        } else {
+            meta("HD", returned, None)
+        }
+    }
+
+    /**
+     * ms: stores the value according to the M mode, which defaults to set.
+     * With C it is only stored, whatever the mode, if the CAS unique matches,
+     * unless I is also given and the supplied CAS is older, then it is stored
+     * as stale.
+     */
+    pub fn meta_set(&mut self, key: &str, value: Bytes, flags: &MetaFlags) -> CommandResult {
+        let data = CommandDto {
+            key: key.to_owned(),
+            value_size_in_bytes: value.len(),
+            value,
+            flags: flags.client_flags.unwrap_or(0),
+            exptime: flags.ttl.unwrap_or(0),
+        };
+        let mode = flags.mode.unwrap_or('S').to_ascii_uppercase();
+
+        let result = match (mode, flags.compare_cas) {
+            ('E', compare_cas) => self.add_comparing(data, compare_cas),
+            ('A', compare_cas) => self.concat(data, false, compare_cas),
+            ('P', compare_cas) => self.concat(data, true, compare_cas),
+            ('R', None) => self.replace(data),
+            // S, the parser rejects any other mode
+            (_, None) => self.set(data),
+            (_, Some(cas_unique)) if flags.invalidate => self.invalidating_cas(data, cas_unique),
+            (_, Some(cas_unique)) => self.cas(data, cas_unique),
+        };
+
+        match result {
+            CommandResult::Stored { cas_unique } => {
+                let mut returned = key_flags(key, flags);
+                if flags.return_cas {
+                    returned.insert(0, format!("c{}", cas_unique));
+                }
+                meta("HD", returned, None)
+            }
+            CommandResult::NotStored => meta("NS", key_flags(key, flags), None),
+            CommandResult::Exists => meta("EX", key_flags(key, flags), None),
+            CommandResult::NotFound => meta("NF", key_flags(key, flags), None),
+            result => result,
+        }
+    }
+
+    /**
+     * cas letting a client whose CAS unique is older than the item's store
+     * its value anyway, marked as stale.
+     */
+    fn invalidating_cas(&mut self, data: CommandDto, cas_unique: u64) -> CommandResult {
+        let counters = &self.stats.counters;
+        counters.cmd_set.incr();
+        let mut unlocked_store = self.store.lock(&data.key);
+
+        match self.live_item(&mut unlocked_store, &data.key) {
+            None => {
+                counters.cas_misses.incr();
+                CommandResult::NotFound
+            }
+            Some(item) if item.cas_unique < cas_unique => {
+                counters.cas_badval.incr();
+                CommandResult::Exists
+            }
+            Some(item) => {
+                let stale = item.cas_unique != cas_unique;
+                counters.cas_hits.incr();
+                let key = data.key.clone();
+                let result = self.insert(&mut unlocked_store, data);
+                if let (true, Some(item)) = (stale, unlocked_store.get_mut(&key)) {
+                    item.stale = true;
+                }
+                result
+            }
+        }
+    }
+
+    /**
+     * md: removes the item, or with I marks it as stale and updates its
+     * expiration time with T.
+     */
+    pub fn meta_delete(&mut self, key: &str, flags: &MetaFlags) -> CommandResult {
+        let counters = &self.stats.counters;
+        let mut unlocked_store = self.store.lock(key);
+
+        let item = match self.live_item(&mut unlocked_store, key) {
+            None => {
+                counters.delete_misses.incr();
+                return meta("NF", key_flags(key, flags), None);
+            }
+            Some(item) => item,
+        };
+        if flags
+            .compare_cas
+            .is_some_and(|cas_unique| cas_unique != item.cas_unique)
+        {
+            return meta("EX", key_flags(key, flags), None);
+        }
+
+        if flags.invalidate {
+            item.invalidate();
+            if let Some(exptime) = flags.ttl {
+                item.touch(exptime);
+            }
+        } else {
+            unlocked_store.remove(key);
+        }
+        counters.delete_hits.incr();
+
+        meta("HD", key_flags(key, flags), None)
+    }
+
+    /**
+     * ma: increments the counter by D, 1 by default, or decrements it in
+     * modes D and -. With N a missing counter is created with the J value.
+     */
+    pub fn meta_arithmetic(&mut self, key: &str, flags: &MetaFlags) -> CommandResult {
+        let delta = flags.delta.unwrap_or(1);
+        let decrement = matches!(flags.mode, Some('D' | 'd' | '-'));
+
+        // the check, the update and the value returned all under one lock
+        let mut unlocked_store = self.store.lock(key);
+
+        if let Some(cas_unique) = flags.compare_cas {
+            match self.live_item(&mut unlocked_store, key) {
+                None => return meta("NF", key_flags(key, flags), None),
+                Some(item) if item.cas_unique != cas_unique => {
+                    return meta("EX", key_flags(key, flags), None)
+                }
+                Some(_) => {}
+            }
+        }
+
+        let mut result = self.change_counter(&mut unlocked_store, key, delta, decrement);
+        if let (CommandResult::NotFound, Some(exptime)) = (&result, flags.vivify) {
+            let value = Bytes::from(flags.initial.unwrap_or(0).to_string());
+            self.stats.counters.cmd_set.incr();
+            result = self.insert(
+                &mut unlocked_store,
+                CommandDto {
+                    key: key.to_owned(),
+                    value_size_in_bytes: value.len(),
+                    value,
+                    flags: 0,
+                    exptime,
+                },
+            );
+        }
+
+        match result {
+            CommandResult::Counter { .. } | CommandResult::Stored { .. } => {}
+            CommandResult::NotFound => return meta("NF", key_flags(key, flags), None),
+            result => return result,
+        }
+        let item = match self.live_item(&mut unlocked_store, key) {
+            None => return meta("NF", key_flags(key, flags), None),
+            Some(item) => item,
+        };
+        if let Some(exptime) = flags.ttl {
+            item.touch(exptime);
+        }
+
+        let mut returned = key_flags(key, flags);
+        if flags.return_ttl {
+            returned.insert(0, format!("t{}", item.ttl()));
+        }
+        if flags.return_cas {
+            returned.insert(0, format!("c{}", item.cas_unique));
+        }
+        if flags.return_value && parse_counter(&item.value).is_some() {
+            meta("VA", returned, Some(item.value.clone()))
+        } else {
+            meta("HD", returned, None)
+        }
+    }
+
+    /**
+     * me: human readable details of the item, for debugging.
+     */
+    pub fn meta_debug(&mut self, key: &str) -> CommandResult {
+        let mut unlocked_store = self.store.lock(key);
+        let class_id = unlocked_store.class_id(key);
+
+        match self.live_item(&mut unlocked_store, key) {
+            None => meta("EN", vec![], None),
+            Some(item) => {
+                let fetched = if item.fetched { "yes" } else { "no" };
+                let details = vec![
+                    key.to_owned(),
+                    format!("exp={}", item.ttl()),
+                    format!("la={}", item.seconds_since_last_access()),
+                    format!("cas={}", item.cas_unique),
+                    format!("fetch={}", fetched),
+                    format!("cls={}", class_id.unwrap()),
+                    format!("size={}", key.len() + item.value_length),
+                ];
+                meta("ME", details, None)
+            }
+        }
+    }
+
+    /**
+     * mn: answers MN, telling a client every quiet command before it in the
+     * pipeline has been processed.
+     */
+    pub fn meta_noop(&self) -> CommandResult {
+        meta("MN", vec![], None)
+    }
+}
+
+fn meta(status: &'static str, flags: Vec<String>, value: Option<Bytes>) -> CommandResult {
+    CommandResult::Meta {
+        status,
+        flags,
+        value,
+    }
+}
+
+/**
+ * Return flags of mg describing the item.
+ */
+fn item_flags(key: &str, item: &Item, flags: &MetaFlags) -> Vec<String> {
+    let mut returned = vec![];
+    if flags.return_cas {
+        returned.push(format!("c{}", item.cas_unique));
+    }
+    if flags.return_flags {
+        returned.push(format!("f{}", item.flags));
+    }
+    if flags.return_hit {
+        returned.push(format!("h{}", u8::from(item.fetched)));
+    }
+    if flags.return_last_access {
+        returned.push(format!("l{}", item.seconds_since_last_access()));
+    }
+    if flags.return_size {
+        returned.push(format!("s{}", item.value_length));
+    }
+    if flags.return_ttl {
+        returned.push(format!("t{}", item.ttl()));
+    }
+    returned.extend(key_flags(key, flags));
+
+    returned
+}
+
+/**
+ * Return flags echoing the request: the key, base64 encoded again when it
+ * came that way, and the opaque token.
+ */
+fn key_flags(key: &str, flags: &MetaFlags) -> Vec<String> {
+    let mut returned = vec![];
+    if flags.return_key {
+        if flags.base64_key {
+            returned.push(format!("k{}", base64::encode(key)));
+            returned.push("b".to_owned());
+        } else {
+            returned.push(format!("k{}", key));
+        }
+    }
+    if let Some(opaque) = &flags.opaque {
+        returned.push(format!("O{}", opaque));
+    }
+
+    returned
+}
@@ -1,7 +1,26 @@
-use crate::errors::*;
+use crate::{errors::*, types::DEFAULT_MEMORY_LIMIT};
 use config::{Config, File};
 use serde::Deserialize;
 
+const DEFAULT_PORT: u16 = 11211;
+/**
+ * Biggest item stored by default, and the range `-I` can set it to.
+ */
+const DEFAULT_MAX_ITEM_SIZE: usize = 1024 * 1024;
+const MIN_ITEM_SIZE: usize = 1024;
+const MAX_ITEM_SIZE: usize = 1024 * 1024 * 1024;
+/**
+ * Defaults of the slab classes: bytes of the smallest chunk besides the item
+ * header (-n), and how much bigger every class is than the previous one (-f).
+ */
+const DEFAULT_MIN_CHUNK_SIZE: usize = 48;
+const DEFAULT_GROWTH_FACTOR: f64 = 1.25;
+/**
+ * Line terminator of the memcached text protocol. Any other separator is a
+ * compatibility mode that has to be set explicitly in the config file.
+ */
+pub const STANDARD_SEPARATOR: &str = "\r\n";
+
 #[derive(Debug, Deserialize)]
 #[allow(unused)]
 struct Server {
@@ -25,11 +44,68 @@ impl Protocol {
             separator: s.get("protocol.separator").unwrap(),
         }
     }
+
+    pub fn is_standard(&self) -> bool {
+        self.separator == STANDARD_SEPARATOR
+    }
 }
 
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol {
+            separator: STANDARD_SEPARATOR.to_string(),
+        }
+    }
+}
+
+/**
+ * Policy choosing the items evicted once the memory limit is reached.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// memcached's segmented LRU, the default
+    Lru,
+    Lfu,
+    WTinyLfu,
+    Random,
+}
+
+impl Eviction {
+    fn parse(name: &str) -> Option<Eviction> {
+        match name {
+            "lru" => Some(Eviction::Lru),
+            "lfu" => Some(Eviction::Lfu),
+            "w-tinylfu" => Some(Eviction::WTinyLfu),
+            "random" => Some(Eviction::Random),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Eviction::Lru => "lru",
+            Eviction::Lfu => "lfu",
+            Eviction::WTinyLfu => "w-tinylfu",
+            Eviction::Random => "random",
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct MyConfig {
     pub port: u16,
     pub protocol: Protocol,
+    /// Largest value accepted, in bytes, which is also the size of the
+    /// slab pages
+    pub max_item_size: usize,
+    /// Bytes the items can take before the least recently used ones are
+    /// evicted
+    pub memory_limit: usize,
+    pub min_chunk_size: usize,
+    pub growth_factor: f64,
+    pub eviction_policy: Eviction,
+    /// Parts the store is split in, one per core by default
+    pub shards: Option<usize>,
 }
 
 pub struct Options {
@@ -46,7 +122,7 @@ impl Options {
 
 impl MyConfig {
     pub fn parse(
-        mut args: impl Iterator<Item = String> + ExactSizeIterator,
+        mut args: impl ExactSizeIterator<Item = String>,
         opt: Option<Options>,
     ) -> Result<MyConfig, Errors> {
         let mut options = opt;
@@ -55,47 +131,163 @@ impl MyConfig {
         }
 
         let s = Config::builder()
-            .add_source(File::with_name(&options.unwrap().config_file))
+            .set_default("server.default_port", DEFAULT_PORT)?
+            .set_default("protocol.separator", STANDARD_SEPARATOR)?
+            .add_source(File::with_name(&options.unwrap().config_file).required(false))
             .build()?;
 
         let protocol = Protocol::create(&s);
 
-        if args.len() != 1 && args.len() != 3 {
+        // the program name comes first, then every option is followed by its value
+        if args.next().is_none() || !args.len().is_multiple_of(2) {
             return Err(Errors::InvalidNumberArguments(String::from(
                 "Invalid number of arguments",
             )));
         }
 
-        args.next();
+        let mut config = MyConfig {
+            port: s.get::<u16>("server.default_port").unwrap(),
+            protocol,
+            max_item_size: DEFAULT_MAX_ITEM_SIZE,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            min_chunk_size: DEFAULT_MIN_CHUNK_SIZE,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            eviction_policy: Eviction::Lru,
+            shards: None,
+        };
 
-        if args.len() == 0 {
-            return Ok(MyConfig {
-                port: s.get::<u16>("server.default_port").unwrap(),
-                protocol,
-            });
+        while let (Some(option), Some(value)) = (args.next(), args.next()) {
+            match option.as_str() {
+                "-p" => {
+                    config.port = value.parse().map_err(|_| {
+                        Errors::InvalidGivenPort(String::from(
+                            "Value given is not a valid port. Provide a integer",
+                        ))
+                    })?
+                }
+                "-I" => {
+                    config.max_item_size = parse_size(&value)
+                        .filter(|size| (MIN_ITEM_SIZE..=MAX_ITEM_SIZE).contains(size))
+                        .ok_or_else(|| {
+                            Errors::InvalidGivenValue(String::from(
+                                "Item size must be between 1k and 1024m",
+                            ))
+                        })?
+                }
+                "-m" => {
+                    config.memory_limit = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|megabytes| *megabytes > 0)
+                        .and_then(|megabytes| megabytes.checked_mul(1024 * 1024))
+                        .ok_or_else(|| {
+                            Errors::InvalidGivenValue(String::from(
+                                "Memory limit must be a positive number of megabytes",
+                            ))
+                        })?
+                }
+                "-n" => {
+                    config.min_chunk_size = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|size| *size > 0)
+                        .ok_or_else(|| {
+                            Errors::InvalidGivenValue(String::from(
+                                "Minimum chunk size must be a positive number of bytes",
+                            ))
+                        })?
+                }
+                "-f" => {
+                    config.growth_factor = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|factor| *factor > 1.0)
+                        .ok_or_else(|| {
+                            Errors::InvalidGivenValue(String::from(
+                                "Growth factor must be greater than 1.0",
+                            ))
+                        })?
+                }
+                "-o" => {
+                    for extended in value.split(',') {
+                        config.set_extended_option(extended)?;
+                    }
+                }
+                _ => {
+                    return Err(Errors::InvalidOptionalArguments(String::from(
+                        "Invalid optional argument",
+                    )))
+                }
+            }
         }
 
-        let option = args.next();
-
-        if option.is_none() || !String::from("-p").eq(&option.unwrap()) {
-            return Err(Errors::InvalidOptionalArguments(String::from(
-                "Invalid optional argument",
+        if config.max_item_size > config.memory_limit / 2 {
+            return Err(Errors::InvalidGivenValue(String::from(
+                "Item size cannot be higher than half of the memory limit",
+            )));
+        }
+        if config
+            .shards
+            .is_some_and(|shards| config.max_item_size > config.memory_limit / shards / 2)
+        {
+            return Err(Errors::InvalidGivenValue(String::from(
+                "Item size cannot be higher than half of the memory of a shard",
             )));
         }
 
-        let port: u16 = match args.next().unwrap().parse() {
-            Err(_) => {
-                return Err(Errors::InvalidGivenPort(String::from(
-                    "Value given is not a valid port. Provide a integer",
+        Ok(config)
+    }
+
+    /**
+     * Applies one of the comma separated `name=value` options given with
+     * `-o`, as memcached's extended options.
+     */
+    fn set_extended_option(&mut self, option: &str) -> Result<(), Errors> {
+        match option.split_once('=') {
+            Some(("eviction_policy", name)) => {
+                self.eviction_policy = Eviction::parse(name).ok_or_else(|| {
+                    Errors::InvalidGivenValue(String::from(
+                        "Eviction policy must be lru, lfu, w-tinylfu or random",
+                    ))
+                })?
+            }
+            Some(("shards", shards)) => {
+                self.shards = Some(
+                    shards
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|shards| *shards > 0)
+                        .ok_or_else(|| {
+                            Errors::InvalidGivenValue(String::from(
+                                "Shards must be a positive number",
+                            ))
+                        })?,
+                )
+            }
+            _ => {
+                return Err(Errors::InvalidOptionalArguments(String::from(
+                    "Invalid extended option",
                 )))
             }
-            Ok(p) => p,
-        };
+        }
 
-        Ok(MyConfig { port, protocol })
+        Ok(())
     }
 }
 
+/**
+ * Parses a size in bytes, optionally followed by a k or m unit as in `512k`.
+ */
+fn parse_size(value: &str) -> Option<usize> {
+    let (digits, unit) = match value.char_indices().last()? {
+        (position, 'k' | 'K') => (&value[..position], 1024),
+        (position, 'm' | 'M') => (&value[..position], 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
 #[cfg(test)]
 mod tests {
     use std::error::Error;
@@ -130,18 +322,18 @@ mod tests {
                 if config.port == s.get::<u16>("server.default_port").unwrap() {
                     Ok(())
                 } else {
-                    Err(String::from(format!(
+                    Err(format!(
                         "Expected port {}, got {}",
                         s.get::<u16>("server.default_port").unwrap(),
                         config.port
-                    ))
+                    )
                     .into())
                 }
             }
-            Err(_) => Err(String::from(format!(
+            Err(_) => Err(format!(
                 "Expected port {}",
                 s.get::<u16>("server.default_port").unwrap()
-            ))
+            )
             .into()),
         }
     }
@@ -161,22 +353,39 @@ mod tests {
                 if config.protocol.separator == s.get::<String>("protocol.separator").unwrap() {
                     Ok(())
                 } else {
-                    Err(String::from(format!(
+                    Err(format!(
                         "Expected separator {}, got {}",
                         s.get::<String>("protocol.separator").unwrap(),
                         config.protocol.separator,
-                    ))
+                    )
                     .into())
                 }
             }
-            Err(_) => Err(String::from(format!(
+            Err(_) => Err(format!(
                 "Expected port {}",
                 s.get::<u16>("server.default_port").unwrap()
-            ))
+            )
             .into()),
         }
     }
 
+    #[test]
+    fn should_use_standard_separator_when_not_configured() -> Result<(), String> {
+        let args = ["myProgram"].iter().map(|s| s.to_string());
+        let options = Some(Options {
+            config_file: "config/missing".to_string(),
+        });
+
+        match MyConfig::parse(args.into_iter(), options) {
+            Ok(config) if config.protocol.is_standard() => Ok(()),
+            Ok(config) => Err(format!(
+                "Expected standard separator, got {:?}",
+                config.protocol.separator
+            )),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
     #[test]
     fn should_fail_when_optional_param_is_given_but_not_value() -> Result<(), String> {
         let args = ["myProgram", "-p"].iter().map(|s| s.to_string());
@@ -216,4 +425,113 @@ mod tests {
             },
         }
     }
+
+    #[test]
+    fn should_parse_options_in_any_order() -> Result<(), String> {
+        let args = ["myProgram", "-I", "2m", "-p", "1234"]
+            .iter()
+            .map(|s| s.to_string());
+
+        match MyConfig::parse(args.into_iter(), None) {
+            Ok(config) if config.port == 1234 && config.max_item_size == 2 * 1024 * 1024 => Ok(()),
+            Ok(_) => Err(String::from("Options were not applied")),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    #[test]
+    fn should_fail_when_item_size_is_out_of_range() -> Result<(), String> {
+        for size in ["512", "2048m", "abc", "k"] {
+            let args = ["myProgram", "-I", size].map(String::from);
+
+            match MyConfig::parse(args.into_iter(), None) {
+                Err(Errors::InvalidGivenValue(_)) => {}
+                _ => return Err(format!("Item size {} should be rejected", size)),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_set_the_memory_limit_in_megabytes() -> Result<(), String> {
+        let args = ["myProgram", "-m", "2"].map(String::from);
+        match MyConfig::parse(args.into_iter(), None) {
+            Ok(config) if config.memory_limit == 2 * 1024 * 1024 => {}
+            Ok(config) => return Err(format!("Unexpected limit {}", config.memory_limit)),
+            Err(err) => return Err(err.to_string()),
+        }
+
+        for args in [["myProgram", "-m", "0"], ["myProgram", "-m", "1"]] {
+            match MyConfig::parse(args.map(String::from).into_iter(), None) {
+                Err(Errors::InvalidGivenValue(_)) => {}
+                _ => return Err(format!("{:?} should be rejected", args)),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_slab_options() -> Result<(), String> {
+        let args = ["myProgram", "-n", "32", "-f", "2"].map(String::from);
+        match MyConfig::parse(args.into_iter(), None) {
+            Ok(config) if config.min_chunk_size == 32 && config.growth_factor == 2.0 => {}
+            Ok(_) => return Err(String::from("Slab options were not applied")),
+            Err(err) => return Err(err.to_string()),
+        }
+
+        for args in [["myProgram", "-f", "1"], ["myProgram", "-n", "0"]] {
+            match MyConfig::parse(args.map(String::from).into_iter(), None) {
+                Err(Errors::InvalidGivenValue(_)) => {}
+                _ => return Err(format!("{:?} should be rejected", args)),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_the_eviction_policy_as_an_extended_option() -> Result<(), String> {
+        let args = ["myProgram", "-o", "eviction_policy=w-tinylfu"].map(String::from);
+        match MyConfig::parse(args.into_iter(), None) {
+            Ok(config) if config.eviction_policy == Eviction::WTinyLfu => {}
+            Ok(_) => return Err(String::from("Eviction policy was not applied")),
+            Err(err) => return Err(err.to_string()),
+        }
+
+        match MyConfig::parse(vec!["myProgram".to_owned()].into_iter(), None) {
+            Ok(config) if config.eviction_policy == Eviction::Lru => {}
+            _ => return Err(String::from("LRU should be the default policy")),
+        }
+
+        for option in ["eviction_policy=fifo", "unknown=1"] {
+            let args = ["myProgram", "-o", option].map(String::from);
+            if MyConfig::parse(args.into_iter(), None).is_ok() {
+                return Err(format!("{} should be rejected", option));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_give_every_shard_room_for_two_items() -> Result<(), String> {
+        let args = ["myProgram", "-m", "8", "-o", "shards=4,eviction_policy=lfu"];
+        match MyConfig::parse(args.map(String::from).into_iter(), None) {
+            Ok(config) if config.shards == Some(4) => {}
+            Ok(_) => return Err(String::from("Shards were not applied")),
+            Err(err) => return Err(err.to_string()),
+        }
+
+        for shards in ["shards=5", "shards=0"] {
+            let args = ["myProgram", "-m", "8", "-o", shards];
+            match MyConfig::parse(args.map(String::from).into_iter(), None) {
+                Err(Errors::InvalidGivenValue(_)) => {}
+                _ => return Err(format!("{} should be rejected", shards)),
+            }
+        }
+
+        Ok(())
+    }
 }
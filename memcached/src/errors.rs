@@ -7,12 +7,19 @@ pub enum Errors {
     InvalidNumberArguments(String),
     InvalidOptionalArguments(String),
     InvalidGivenPort(String),
+    InvalidGivenValue(String),
     ConfigDataParseError(ConfigError),
 }
 
 impl fmt::Display for Errors {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Errors::InvalidNumberArguments(message)
+            | Errors::InvalidOptionalArguments(message)
+            | Errors::InvalidGivenPort(message)
+            | Errors::InvalidGivenValue(message) => write!(f, "{}", message),
+            Errors::ConfigDataParseError(error) => write!(f, "{}", error),
+        }
     }
 }
 
@@ -23,3 +30,36 @@ impl From<ConfigError> for Errors {
         Errors::ConfigDataParseError(error)
     }
 }
+
+/**
+ * Reasons a request cannot be processed. Its `Display` is the response line
+ * sent back to the client, without the line terminator.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// Nonexistent command name or wrong number of arguments
+    UnknownCommand,
+    BadCommandLineFormat,
+    BadDataChunk,
+    LineTooLong,
+    InvalidNumericDelta,
+    /// The request is valid but the server cannot fulfil it
+    ServerError(&'static str),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownCommand => write!(f, "ERROR"),
+            ParseError::BadCommandLineFormat => write!(f, "CLIENT_ERROR bad command line format"),
+            ParseError::BadDataChunk => write!(f, "CLIENT_ERROR bad data chunk"),
+            ParseError::LineTooLong => write!(f, "CLIENT_ERROR line too long"),
+            ParseError::InvalidNumericDelta => {
+                write!(f, "CLIENT_ERROR invalid numeric delta argument")
+            }
+            ParseError::ServerError(message) => write!(f, "SERVER_ERROR {}", message),
+        }
+    }
+}
+
+impl Error for ParseError {}
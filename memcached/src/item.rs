@@ -1,49 +1,177 @@
+use bytes::Bytes;
 use chrono::prelude::*;
-use std::{
-    ops::{Add, Sub},
-    time::Duration,
-};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/**
+ * Longest expiration time taken as seconds from now. Anything bigger is an
+ * absolute Unix time, as in memcached.
+ */
+const MAX_RELATIVE_EXPTIME: isize = 60 * 60 * 24 * 30;
+
+/**
+ * Bytes taken by every item besides its key and value: the header memcached
+ * keeps for each of them, CAS unique included.
+ */
+pub const ITEM_HEADER_SIZE: usize = 56;
+
+/**
+ * Last CAS unique handed out. It is shared by the whole server so any two
+ * versions of an item, whatever the key, never get the same token.
+ */
+static LAST_CAS_UNIQUE: AtomicU64 = AtomicU64::new(0);
+
+fn next_cas_unique() -> u64 {
+    LAST_CAS_UNIQUE.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/**
+ * CAS unique of the item stored last. Every item stored up to now has a
+ * lower or equal token, which is how flush_all tells them apart without
+ * walking the whole store.
+ */
+pub fn last_cas_unique() -> u64 {
+    LAST_CAS_UNIQUE.load(Ordering::Relaxed)
+}
 
 #[derive(Debug, Clone)]
 pub struct Item {
     pub flags: u16,
-    exptime: i64,
-    pub value: String,
+    /// Unix time the item expires at, `None` if it never does
+    exptime: Option<i64>,
+    pub value: Bytes,
     pub value_length: usize,
+    pub cas_unique: u64,
+    /// Whether the item was ever returned by a retrieval command
+    pub fetched: bool,
+    last_accessed_at: i64,
+    /// Invalidated by a meta command, still served until it is recached
+    pub stale: bool,
+    /// Whether a client was already told to recache this item
+    pub win_token_sent: bool,
 }
 
 impl Item {
-    pub fn new(flags: u16, exptime: isize, value_length: usize, value: String) -> Self {
-        let mut will_expire_on = Utc::now();
-
-        if exptime < 0 {
-            will_expire_on = will_expire_on.sub(Duration::new(1, 0));
-        } else {
-            will_expire_on = will_expire_on.add(Duration::new(exptime as u64, 0));
-        }
-
+    pub fn new(flags: u16, exptime: isize, value_length: usize, value: Bytes) -> Self {
         Item {
             flags,
-            exptime: will_expire_on.timestamp(),
+            exptime: expiration_timestamp(exptime),
             value_length,
             value,
+            cas_unique: next_cas_unique(),
+            fetched: false,
+            last_accessed_at: Utc::now().timestamp(),
+            stale: false,
+            win_token_sent: false,
         }
     }
 
+    /**
+     * Records the item was returned to a client.
+     */
+    pub fn fetch(&mut self) {
+        self.fetched = true;
+        self.last_accessed_at = Utc::now().timestamp();
+    }
+
+    /**
+     * Marks the item as stale, giving it a new CAS unique so pending cas
+     * updates based on the old version fail.
+     */
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+        self.cas_unique = next_cas_unique();
+    }
+
+    /**
+     * Seconds left before the item expires, -1 if it never does.
+     */
+    pub fn ttl(&self) -> i64 {
+        match self.exptime {
+            None => -1,
+            Some(exptime) => (exptime - Utc::now().timestamp()).max(0),
+        }
+    }
+
+    /**
+     * Unix time the item expires at, `None` if it never does.
+     */
+    pub fn exptime(&self) -> Option<i64> {
+        self.exptime
+    }
+
+    /**
+     * Unix time the item was last fetched or touched at.
+     */
+    pub fn last_accessed_at(&self) -> i64 {
+        self.last_accessed_at
+    }
+
+    pub fn seconds_since_last_access(&self) -> i64 {
+        Utc::now().timestamp() - self.last_accessed_at
+    }
+
+    /**
+     * Bytes the item takes in the store under `key`.
+     */
+    pub fn size(&self, key: &str) -> usize {
+        ITEM_HEADER_SIZE + key.len() + self.value_length
+    }
+
+    /**
+     * Replaces the value in place, keeping flags and expiration time.
+     */
+    pub fn update_value(&mut self, value: Bytes) {
+        self.value_length = value.len();
+        self.value = value;
+        self.cas_unique = next_cas_unique();
+    }
+
+    /**
+     * Sets a new expiration time, leaving the value and its CAS unique as
+     * they are.
+     */
+    pub fn touch(&mut self, exptime: isize) {
+        self.exptime = expiration_timestamp(exptime);
+        self.last_accessed_at = Utc::now().timestamp();
+    }
+
+    /**
+     * Whether its expiration time is past. Items invalidated by flush_all are
+     * told by their store instead.
+     */
     pub fn expired(&self) -> bool {
         let now = Utc::now().timestamp();
 
-        self.exptime < now
+        self.exptime.is_some_and(|exptime| exptime <= now)
     }
 }
 
+/**
+ * Turns the exptime of a command into the Unix time the item expires at: 0
+ * means never, negative values right away, up to 30 days is relative to now
+ * and anything else is already a Unix time.
+ */
+pub fn expiration_timestamp(exptime: isize) -> Option<i64> {
+    let now = Utc::now().timestamp();
+
+    match exptime {
+        0 => None,
+        exptime if exptime < 0 => Some(now),
+        exptime if exptime > MAX_RELATIVE_EXPTIME => Some(exptime as i64),
+        exptime => Some(now + exptime as i64),
+    }
+}
+
+#[cfg(test)]
 pub mod tests {
+    use bytes::Bytes;
+
     use super::Item;
 
     pub struct ItemBuilder {
         flags: u16,
-        exptime: i64,
-        value: String,
+        exptime: Option<i64>,
+        value: Bytes,
         value_length: usize,
     }
 
@@ -51,8 +179,8 @@ pub mod tests {
         pub fn new() -> ItemBuilder {
             ItemBuilder {
                 flags: 0,
-                exptime: 100,
-                value: String::from("myValue"),
+                exptime: None,
+                value: Bytes::from("myValue"),
                 value_length: 7,
             }
         }
@@ -61,9 +189,65 @@ pub mod tests {
             Item {
                 flags: self.flags,
                 exptime: self.exptime,
-                value: self.value.to_owned(),
+                value: self.value.clone(),
                 value_length: self.value_length,
+                cas_unique: 1,
+                fetched: false,
+                last_accessed_at: 0,
+                stale: false,
+                win_token_sent: false,
             }
         }
     }
 }
+
+#[cfg(test)]
+mod item_tests {
+    use super::*;
+
+    #[test]
+    fn should_assign_increasing_cas_unique_on_every_mutation() {
+        let mut item = Item::new(0, 100, 4, Bytes::from("hola"));
+        let other = Item::new(0, 100, 4, Bytes::from("hola"));
+        assert!(other.cas_unique > item.cas_unique);
+
+        item.update_value(Bytes::from("hello"));
+        assert!(item.cas_unique > other.cas_unique);
+        assert_eq!(item.value_length, 5);
+    }
+
+    #[test]
+    fn should_change_expiration_time_when_touched() {
+        let mut item = Item::new(0, 100, 4, Bytes::from("hola"));
+        let cas_unique = item.cas_unique;
+
+        item.touch(-1);
+        assert!(item.expired());
+
+        item.touch(100);
+        assert!(!item.expired());
+        assert_eq!(item.cas_unique, cas_unique);
+    }
+
+    #[test]
+    fn should_follow_memcached_expiration_time_semantics() {
+        let now = Utc::now().timestamp() as isize;
+
+        let item = Item::new(0, 0, 4, Bytes::from("hola"));
+        assert!(!item.expired());
+        assert_eq!(item.ttl(), -1);
+
+        assert!(Item::new(0, -1, 4, Bytes::from("hola")).expired());
+
+        let item = Item::new(0, MAX_RELATIVE_EXPTIME, 4, Bytes::from("hola"));
+        assert!(
+            (MAX_RELATIVE_EXPTIME as i64 - 1..=MAX_RELATIVE_EXPTIME as i64).contains(&item.ttl())
+        );
+
+        let item = Item::new(0, now + 100, 4, Bytes::from("hola"));
+        assert!(!item.expired());
+        assert!((99..=100).contains(&item.ttl()));
+
+        assert!(Item::new(0, now - 100, 4, Bytes::from("hola")).expired());
+    }
+}
@@ -1,27 +1,41 @@
+mod binary_protocol;
 mod commands;
 mod config;
 mod errors;
 mod item;
+mod logging;
 mod protocol_parser;
+mod stats;
 mod store_manager;
 mod types;
 
 use bytes::BytesMut;
-use std::{
-    collections::HashMap,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
-};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::tcp::WriteHalf,
+    io::{self, AsyncReadExt, AsyncWriteExt},
+    net::tcp::{ReadHalf, WriteHalf},
 };
 
-use commands::CommandDto;
+use commands::{CommandDto, CommandResult};
+use stats::{ConnectionActivity, Stats};
 use types::Store;
 
-use crate::{commands::Commands, config::MyConfig, protocol_parser::CommandParserInputDataBuilder};
+use crate::{
+    commands::Commands,
+    config::Protocol,
+    errors::ParseError,
+    protocol_parser::{
+        CommandParserInputData, CommandParserInputDataBuilder, CrawlerCommand, FrameDecoder,
+        ResponseEncoder,
+    },
+};
+
+pub use config::MyConfig;
+pub use item::Item;
+pub use store_manager::{Cursor, ScannedItem, ShardedStore};
+
+const READ_BUFFER_CAPACITY: usize = 4096;
 
 pub struct Server {}
 
@@ -37,15 +51,16 @@ impl Server {
     }
 
     pub async fn run(&self) {
-        tracing_subscriber::fmt()
-            .with_writer(std::io::stderr)
-            .init();
+        logging::init();
 
         let config = match MyConfig::parse(std::env::args(), None) {
             Ok(c) => c,
             Err(err) => panic!("Invalid arguments {:?}", err),
         };
-        let store = Arc::new(Mutex::new(HashMap::new()));
+        let store = Arc::new(ShardedStore::new(&config));
+        let stats = Arc::new(Stats::new());
+        tokio::spawn(store_manager::maintainer::run(store.clone()));
+        tokio::spawn(store_manager::crawler::run(store.clone(), stats.clone()));
 
         let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], config.port)))
             .await
@@ -54,110 +69,379 @@ impl Server {
         tracing::info!("Listening on port {}", config.port);
 
         loop {
-            let (socket, _) = listener.accept().await.unwrap();
+            let (socket, addr) = listener.accept().await.unwrap();
             tracing::info!("new connection established");
             let store = store.clone();
+            let stats = stats.clone();
+            let config = config.clone();
 
             tokio::spawn(async move {
-                handle_connection(socket, store).await;
+                let connection = stats.connection_opened(addr);
+                handle_connection(socket, store, stats.clone(), config, &connection).await;
+                stats.connection_closed(&connection);
             });
         }
     }
 }
 
-async fn response<'a>(writer: &mut WriteHalf<'a>, response: &str) {
-    writer.write_all(response.as_bytes()).await.unwrap();
-    writer.flush().await.unwrap();
-}
+/**
+ * Serves a client speaking either protocol, told apart by the first byte it
+ * sends: binary requests always start with their magic byte.
+ */
+async fn handle_connection(
+    mut stream: TcpStream,
+    store: Store,
+    stats: Arc<Stats>,
+    config: MyConfig,
+    activity: &ConnectionActivity,
+) {
+    let protocol = config.protocol.clone();
+    let max_item_size = config.max_item_size;
+    let mut commands = Commands::new(store, stats.clone(), config);
+    let (rd, wr) = stream.split();
+    let mut connection = Connection {
+        rd,
+        wr,
+        buf: BytesMut::with_capacity(READ_BUFFER_CAPACITY),
+        stats: &stats,
+        activity,
+    };
+
+    if !connection.read_more().await {
+        return;
+    }
 
-async fn handle_connection(mut stream: TcpStream, store: Store) {
-    let mut commands = Commands::new(store);
-    // TODO: refactor this
-    let config = match MyConfig::parse(std::env::args(), None) {
-        Ok(c) => c,
-        Err(err) => panic!("Invalid arguments {:?}", err),
+    let result = if connection.buf[0] == binary_protocol::REQUEST_MAGIC {
+        tracing::info!("client speaks the binary protocol");
+        connection.serve_binary(&mut commands, max_item_size).await
+    } else {
+        connection
+            .serve_text(&mut commands, protocol, max_item_size)
+            .await
     };
-    let builder = CommandParserInputDataBuilder::new(config.protocol);
-    let (mut rd, mut wr) = stream.split();
-    loop {
-        let mut buf = BytesMut::with_capacity(1024);
-        rd.read_buf(&mut buf).await.unwrap();
-        let command = String::from_utf8(buf.to_vec()).unwrap();
-
-        let input_data = builder.build(command);
-        if input_data.is_err() {
-            // TODO: deal with different errors and return different messages
-            let test = input_data.err();
-            tracing::warn!(target: "Wrong command", warning = "Wrong command", "~~~ {:?}",  test);
-            response(&mut wr, "wrong command").await;
-            continue;
+
+    if let Err(err) = result {
+        tracing::warn!("error writing to connection: {:?}", err);
+    }
+}
+
+struct Connection<'a> {
+    rd: ReadHalf<'a>,
+    wr: WriteHalf<'a>,
+    buf: BytesMut,
+    stats: &'a Stats,
+    activity: &'a ConnectionActivity,
+}
+
+impl<'a> Connection<'a> {
+    async fn serve_text(
+        &mut self,
+        commands: &mut Commands,
+        protocol: Protocol,
+        max_item_size: usize,
+    ) -> io::Result<()> {
+        let mut decoder = FrameDecoder::new(protocol.clone(), max_item_size);
+        let encoder = ResponseEncoder::new(protocol);
+        let builder = CommandParserInputDataBuilder::new();
+
+        loop {
+            loop {
+                let result = match decoder.decode(&mut self.buf) {
+                    Ok(None) => break,
+                    Ok(Some(frame)) => {
+                        self.stats.command_received(self.activity);
+                        match builder.build(frame) {
+                            Ok(input_data) if input_data.command == "quit" => {
+                                tracing::info!("connection closed by the client");
+                                return Ok(());
+                            }
+                            Ok(CommandParserInputData {
+                                crawler: Some(CrawlerCommand::Metadump { class }),
+                                ..
+                            }) => {
+                                self.metadump(commands, &encoder, class).await?;
+                                None
+                            }
+                            Ok(input_data) => handle_command(commands, input_data),
+                            Err(err) => Some(handle_error(err)),
+                        }
+                    }
+                    Err(err) => Some(handle_error(err)),
+                };
+
+                if let Some(result) = result {
+                    self.respond(&encoder.encode(&result)).await?;
+                }
+            }
+
+            if !self.read_more().await {
+                return Ok(());
+            }
         }
-        let input_data = input_data.unwrap();
-        if input_data.command == "set" {
-            let result = commands.set(CommandDto {
-                key: input_data.key,
-                value: input_data.value.unwrap(),
-                flags: input_data.flags.unwrap(),
-                exptime: input_data.exptime.unwrap(),
-                value_size_in_bytes: input_data.value_size_bytes.unwrap(),
-            });
-            tracing::info!("set result: {:?}", result);
+    }
+
+    async fn serve_binary(
+        &mut self,
+        commands: &mut Commands,
+        max_item_size: usize,
+    ) -> io::Result<()> {
+        let mut decoder = binary_protocol::Decoder::new(max_item_size);
+
+        loop {
+            loop {
+                let reply = match decoder.decode(&mut self.buf) {
+                    Ok(None) => break,
+                    Ok(Some(request)) => {
+                        self.stats.command_received(self.activity);
+                        binary_protocol::handle_request(commands, request)
+                    }
+                    Err(err @ binary_protocol::DecodeError::InvalidMagic(_)) => {
+                        tracing::warn!("closing binary connection: {:?}", err);
+                        return Ok(());
+                    }
+                    Err(err) => {
+                        tracing::warn!(target: "Wrong command", "~~~ {:?}", err);
+                        binary_protocol::invalid_request(&err)
+                    }
+                };
 
-            if input_data.no_reply == Some(false) {
-                response(&mut wr, &result).await;
+                if let Some(response) = reply.response {
+                    self.respond(&response).await?;
+                }
+                if reply.close {
+                    tracing::info!("connection closed by the client");
+                    return Ok(());
+                }
             }
-        } else if input_data.command == "get" {
-            let result = commands.get(input_data.key.as_str());
-            tracing::info!("get result: {:?}", result);
-            response(&mut wr, &result).await;
-        } else if input_data.command == "add" {
-            let result = commands.add(CommandDto {
-                key: input_data.key,
-                value: input_data.value.unwrap(),
-                flags: input_data.flags.unwrap(),
-                exptime: input_data.exptime.unwrap(),
-                value_size_in_bytes: input_data.value_size_bytes.unwrap(),
-            });
-            tracing::info!("add result: {:?}", result);
-            if input_data.no_reply == Some(false) {
-                response(&mut wr, &result).await;
+
+            if !self.read_more().await {
+                return Ok(());
             }
-        } else if input_data.command == "replace" {
-            let result = commands.replace(CommandDto {
-                key: input_data.key,
-                value: input_data.value.unwrap(),
-                flags: input_data.flags.unwrap(),
-                exptime: input_data.exptime.unwrap(),
-                value_size_in_bytes: input_data.value_size_bytes.unwrap(),
-            });
-            tracing::info!("replace result: {:?}", result);
-            if input_data.no_reply == Some(false) {
-                response(&mut wr, &result).await;
+        }
+    }
+
+    /**
+     * Streams the lines of lru_crawler metadump a page at a time, the store
+     * being unlocked while each of them is sent.
+     */
+    async fn metadump(
+        &mut self,
+        commands: &Commands,
+        encoder: &ResponseEncoder,
+        class: Option<u64>,
+    ) -> io::Result<()> {
+        let mut cursor = Some(Cursor::default());
+        while let Some(page) = cursor {
+            let (lines, next) = match commands.metadump(page, class) {
+                Ok(page) => page,
+                Err(err) => return self.respond(&encoder.error(&err)).await,
+            };
+            if !lines.is_empty() {
+                self.respond(&encoder.lines(lines.iter().map(String::as_str)))
+                    .await?;
             }
-        } else if input_data.command == "append" {
-            let result = commands.append(CommandDto {
-                key: input_data.key,
-                value: input_data.value.unwrap(),
-                flags: input_data.flags.unwrap(),
-                exptime: input_data.exptime.unwrap(),
-                value_size_in_bytes: input_data.value_size_bytes.unwrap(),
-            });
-            tracing::info!("append result: {:?}", result);
-            if input_data.no_reply == Some(false) {
-                response(&mut wr, &result).await;
+            cursor = next;
+        }
+
+        self.respond(&encoder.lines(["END"])).await
+    }
+
+    /**
+     * Reads more data from the client, returning `false` once the connection
+     * is closed.
+     */
+    async fn read_more(&mut self) -> bool {
+        match self.rd.read_buf(&mut self.buf).await {
+            Ok(0) => {
+                tracing::info!("connection closed");
+                false
+            }
+            Ok(read) => {
+                self.stats.counters.bytes_read.add(read as u64);
+                true
             }
-        } else if input_data.command == "prepend" {
-            let result = commands.prepend(CommandDto {
+            Err(err) => {
+                tracing::warn!("error reading from connection: {:?}", err);
+                false
+            }
+        }
+    }
+
+    async fn respond(&mut self, response: &[u8]) -> io::Result<()> {
+        self.wr.write_all(response).await?;
+        self.wr.flush().await?;
+        self.stats.counters.bytes_written.add(response.len() as u64);
+
+        Ok(())
+    }
+}
+
+fn handle_error(error: ParseError) -> CommandResult {
+    tracing::warn!(target: "Wrong command", warning = "Wrong command", "~~~ {:?}", error);
+    CommandResult::Error(error)
+}
+
+/**
+ * Runs the command, returning the response to send back unless the client
+ * asked for no reply.
+ */
+fn handle_command(
+    commands: &mut Commands,
+    input_data: CommandParserInputData,
+) -> Option<CommandResult> {
+    if input_data.command == "set" {
+        let result = commands.set(CommandDto {
+            key: input_data.key,
+            value: input_data.value.unwrap(),
+            flags: input_data.flags.unwrap(),
+            exptime: input_data.exptime.unwrap(),
+            value_size_in_bytes: input_data.value_size_bytes.unwrap(),
+        });
+        tracing::info!("set result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "get" {
+        let result = commands.get(&input_data.keys);
+        tracing::info!("get result: {:?}", result);
+        Some(result)
+    } else if input_data.command == "gets" {
+        let result = commands.gets(&input_data.keys);
+        tracing::info!("gets result: {:?}", result);
+        Some(result)
+    } else if input_data.command == "add" {
+        let result = commands.add(CommandDto {
+            key: input_data.key,
+            value: input_data.value.unwrap(),
+            flags: input_data.flags.unwrap(),
+            exptime: input_data.exptime.unwrap(),
+            value_size_in_bytes: input_data.value_size_bytes.unwrap(),
+        });
+        tracing::info!("add result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "replace" {
+        let result = commands.replace(CommandDto {
+            key: input_data.key,
+            value: input_data.value.unwrap(),
+            flags: input_data.flags.unwrap(),
+            exptime: input_data.exptime.unwrap(),
+            value_size_in_bytes: input_data.value_size_bytes.unwrap(),
+        });
+        tracing::info!("replace result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "append" {
+        let result = commands.append(CommandDto {
+            key: input_data.key,
+            value: input_data.value.unwrap(),
+            flags: input_data.flags.unwrap(),
+            exptime: input_data.exptime.unwrap(),
+            value_size_in_bytes: input_data.value_size_bytes.unwrap(),
+        });
+        tracing::info!("append result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "prepend" {
+        let result = commands.prepend(CommandDto {
+            key: input_data.key,
+            value: input_data.value.unwrap(),
+            flags: input_data.flags.unwrap(),
+            exptime: input_data.exptime.unwrap(),
+            value_size_in_bytes: input_data.value_size_bytes.unwrap(),
+        });
+        tracing::info!("prepend result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "cas" {
+        let result = commands.cas(
+            CommandDto {
                 key: input_data.key,
                 value: input_data.value.unwrap(),
                 flags: input_data.flags.unwrap(),
                 exptime: input_data.exptime.unwrap(),
                 value_size_in_bytes: input_data.value_size_bytes.unwrap(),
-            });
-            tracing::info!("prepend result: {:?}", result);
-            if input_data.no_reply == Some(false) {
-                response(&mut wr, &result).await;
-            }
-        }
+            },
+            input_data.cas_unique.unwrap(),
+        );
+        tracing::info!("cas result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "delete" {
+        let result = commands.delete(&input_data.key);
+        tracing::info!("delete result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "gat" {
+        let result = commands.gat(input_data.exptime.unwrap(), &input_data.keys);
+        tracing::info!("gat result: {:?}", result);
+        Some(result)
+    } else if input_data.command == "gats" {
+        let result = commands.gats(input_data.exptime.unwrap(), &input_data.keys);
+        tracing::info!("gats result: {:?}", result);
+        Some(result)
+    } else if input_data.command == "touch" {
+        let result = commands.touch(&input_data.key, input_data.exptime.unwrap());
+        tracing::info!("touch result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "flush_all" {
+        let result = commands.flush_all(input_data.exptime.unwrap());
+        tracing::info!("flush_all result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "incr" {
+        let result = commands.incr(&input_data.key, input_data.delta.unwrap());
+        tracing::info!("incr result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "decr" {
+        let result = commands.decr(&input_data.key, input_data.delta.unwrap());
+        tracing::info!("decr result: {:?}", result);
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "version" {
+        Some(commands.version())
+    } else if input_data.command == "verbosity" {
+        let result = match input_data.verbosity {
+            Some(verbosity) => commands.verbosity(verbosity),
+            None => CommandResult::Error(ParseError::BadCommandLineFormat),
+        };
+        reply(result, input_data.no_reply)
+    } else if input_data.command == "stats" {
+        Some(commands.stats(&input_data.key))
+    } else if input_data.command == "mg" {
+        let flags = input_data.meta_flags.unwrap();
+        let result = commands.meta_get(&input_data.key, &flags);
+        tracing::info!("mg result: {:?}", result);
+        meta_reply(result, flags.quiet, &["EN"])
+    } else if input_data.command == "ms" {
+        let flags = input_data.meta_flags.unwrap();
+        let result = commands.meta_set(&input_data.key, input_data.value.unwrap(), &flags);
+        tracing::info!("ms result: {:?}", result);
+        meta_reply(result, flags.quiet, &["HD"])
+    } else if input_data.command == "md" {
+        let flags = input_data.meta_flags.unwrap();
+        let result = commands.meta_delete(&input_data.key, &flags);
+        tracing::info!("md result: {:?}", result);
+        meta_reply(result, flags.quiet, &["HD", "NF"])
+    } else if input_data.command == "ma" {
+        let flags = input_data.meta_flags.unwrap();
+        let result = commands.meta_arithmetic(&input_data.key, &flags);
+        tracing::info!("ma result: {:?}", result);
+        meta_reply(result, flags.quiet, &["HD", "NF"])
+    } else if input_data.command == "me" {
+        Some(commands.meta_debug(&input_data.key))
+    } else if input_data.command == "mn" {
+        Some(commands.meta_noop())
+    } else {
+        None
+    }
+}
+
+/**
+ * In quiet mode meta commands leave out the responses a pipeline does not
+ * care about, which are listed in `hidden`. Errors are always sent.
+ */
+fn meta_reply(result: CommandResult, quiet: bool, hidden: &[&str]) -> Option<CommandResult> {
+    match &result {
+        CommandResult::Meta { status, .. } if quiet && hidden.contains(status) => None,
+        _ => Some(result),
     }
 }
+
+fn reply(result: CommandResult, no_reply: Option<bool>) -> Option<CommandResult> {
+    if no_reply == Some(true) {
+        return None;
+    }
+
+    Some(result)
+}
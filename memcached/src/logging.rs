@@ -0,0 +1,50 @@
+use std::sync::OnceLock;
+
+use tracing_subscriber::{
+    filter::LevelFilter, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, Registry,
+};
+
+/**
+ * Verbosity the server starts with, logging connections and commands.
+ */
+const DEFAULT_VERBOSITY: u32 = 1;
+
+static FILTER: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/**
+ * Installs the global subscriber, writing to stderr, with a level that can be
+ * changed later on by `set_verbosity`.
+ */
+pub fn init() {
+    let (filter, handle) = reload::Layer::new(level(DEFAULT_VERBOSITY));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .init();
+
+    FILTER.set(handle).unwrap();
+}
+
+/**
+ * Changes the level of the active subscriber: 0 only logs warnings and errors,
+ * every increment shows one more level of detail.
+ */
+pub fn set_verbosity(verbosity: u32) {
+    match FILTER.get() {
+        None => tracing::warn!("no subscriber to change the verbosity of"),
+        Some(handle) => {
+            if let Err(err) = handle.reload(level(verbosity)) {
+                tracing::warn!("error changing the verbosity: {:?}", err);
+            }
+        }
+    }
+}
+
+fn level(verbosity: u32) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
@@ -0,0 +1,473 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::{
+    config::Protocol,
+    errors::ParseError,
+    types::{META_SET_COMMAND, WRITE_COMMANDS},
+};
+
+/**
+ * Position of `<bytes>` in a storage command line:
+ * <command name> <key> <flags> <exptime> <bytes> [noreply]
+ */
+const BYTES_TOKEN_POSITION: usize = 4;
+/**
+ * Position of `<datalen>` in a meta set command line:
+ * ms <key> <datalen> <flags>*
+ */
+const META_BYTES_TOKEN_POSITION: usize = 2;
+
+/**
+ * Longest command line accepted. Anything longer without a terminator is
+ * discarded so a misbehaving client cannot make the buffer grow forever.
+ */
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub line: String,
+    pub data: Option<Bytes>,
+}
+
+#[derive(Debug)]
+enum State {
+    Line,
+    /// Command line already received whose data block is still incomplete
+    Data {
+        line: String,
+        data_size: usize,
+    },
+    /// Discarding input up to the next line terminator after an error
+    Swallow,
+    /// Discarding the rest of a data block too large to be stored
+    SwallowData {
+        remaining: usize,
+    },
+}
+
+/**
+ * Splits the bytes received on a connection into frames, one per command.
+ * It keeps its state between reads, so commands split across several
+ * segments, several commands sent at once, and data blocks bigger than a
+ * single read are all handled.
+ */
+#[derive(Debug)]
+pub struct FrameDecoder {
+    protocol: Protocol,
+    max_item_size: usize,
+    state: State,
+}
+
+impl FrameDecoder {
+    pub fn new(protocol: Protocol, max_item_size: usize) -> FrameDecoder {
+        FrameDecoder {
+            protocol,
+            max_item_size,
+            state: State::Line,
+        }
+    }
+
+    /**
+     * Returns the next complete frame in `buf`, consuming its bytes, or `None`
+     * when more data must be read first.
+     *
+     * After an error the decoder resynchronises on its own: the rest of the
+     * offending line is discarded and decoding resumes with the next one.
+     * Data blocks bigger than the maximum item size are discarded as they
+     * arrive, without ever being buffered whole.
+     */
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Frame>, ParseError> {
+        loop {
+            match self.state {
+                State::Swallow => {
+                    if !self.swallow_line(buf) {
+                        return Ok(None);
+                    }
+                    self.state = State::Line;
+                }
+                State::SwallowData { remaining } => {
+                    let discarded = remaining.min(buf.len());
+                    buf.advance(discarded);
+                    if discarded < remaining {
+                        self.state = State::SwallowData {
+                            remaining: remaining - discarded,
+                        };
+                        return Ok(None);
+                    }
+                    self.state = State::Line;
+                }
+                State::Line => {
+                    let line = match self.decode_line(buf)? {
+                        None => return Ok(None),
+                        Some(line) => line,
+                    };
+
+                    match data_size(&line) {
+                        None => return Ok(Some(Frame { line, data: None })),
+                        Some(data_size) if data_size > self.max_item_size => {
+                            tracing::info!("data block too large for {}", line);
+                            self.state = State::SwallowData {
+                                remaining: data_size + self.protocol.separator.len(),
+                            };
+                            return Err(ParseError::ServerError("object too large for cache"));
+                        }
+                        Some(data_size) => self.state = State::Data { line, data_size },
+                    }
+                }
+                State::Data { .. } => return self.decode_data(buf),
+            }
+        }
+    }
+
+    fn line_terminator(&self) -> &[u8] {
+        // The standard protocol also accepts lines ended by a bare "\n"
+        if self.protocol.is_standard() {
+            b"\n"
+        } else {
+            self.protocol.separator.as_bytes()
+        }
+    }
+
+    fn decode_line(&mut self, buf: &mut BytesMut) -> Result<Option<String>, ParseError> {
+        let terminator = self.line_terminator();
+        let position = match find(buf, terminator) {
+            None => {
+                if buf.len() > MAX_LINE_LENGTH {
+                    self.discard_incomplete_line(buf);
+                    self.state = State::Swallow;
+                    return Err(ParseError::LineTooLong);
+                }
+                return Ok(None);
+            }
+            Some(position) => position,
+        };
+
+        let mut line = buf.split_to(position);
+        buf.advance(terminator.len());
+        if self.protocol.is_standard() && line.ends_with(b"\r") {
+            line.truncate(line.len() - 1);
+        }
+
+        match String::from_utf8(line.to_vec()) {
+            Ok(line) => Ok(Some(line)),
+            Err(_) => Err(ParseError::BadCommandLineFormat),
+        }
+    }
+
+    fn decode_data(&mut self, buf: &mut BytesMut) -> Result<Option<Frame>, ParseError> {
+        let separator = self.protocol.separator.as_bytes();
+        let data_size = match self.state {
+            State::Data { data_size, .. } => data_size,
+            _ => unreachable!("decode_data called without a pending data block"),
+        };
+
+        if buf.len() < data_size + separator.len() {
+            buf.reserve(data_size + separator.len() - buf.len());
+            return Ok(None);
+        }
+
+        let line = match std::mem::replace(&mut self.state, State::Line) {
+            State::Data { line, .. } => line,
+            _ => unreachable!(),
+        };
+        let data = buf.split_to(data_size).freeze();
+
+        if !buf.starts_with(separator) {
+            // The data block is longer than announced, skip what is left of it
+            tracing::info!("bad data chunk for {}", line);
+            self.state = State::Swallow;
+            return Err(ParseError::BadDataChunk);
+        }
+        buf.advance(separator.len());
+
+        Ok(Some(Frame {
+            line,
+            data: Some(data),
+        }))
+    }
+
+    /**
+     * Discards input up to and including the next line terminator, returning
+     * whether it was found.
+     */
+    fn swallow_line(&self, buf: &mut BytesMut) -> bool {
+        let terminator = self.line_terminator();
+        match find(buf, terminator) {
+            None => {
+                self.discard_incomplete_line(buf);
+                false
+            }
+            Some(position) => {
+                buf.advance(position + terminator.len());
+                true
+            }
+        }
+    }
+
+    /**
+     * Drops the buffered bytes, keeping only those that could be the start of
+     * a terminator split across reads.
+     */
+    fn discard_incomplete_line(&self, buf: &mut BytesMut) {
+        let keep = self.line_terminator().len() - 1;
+        buf.advance(buf.len().saturating_sub(keep));
+    }
+}
+
+/**
+ * Parses the `<bytes>` argument of a storage command. Sizes that do not fit
+ * a signed 32 bits integer are rejected, as memcached does.
+ */
+pub fn parse_data_size(token: &str) -> Option<usize> {
+    token
+        .parse::<i32>()
+        .ok()
+        .and_then(|size| usize::try_from(size).ok())
+}
+
+/**
+ * Size of the data block following `line`, if the command carries one.
+ */
+fn data_size(line: &str) -> Option<usize> {
+    let mut tokens = line.split_whitespace();
+    let command = tokens.next()?;
+
+    let position = if command == META_SET_COMMAND {
+        META_BYTES_TOKEN_POSITION
+    } else if WRITE_COMMANDS.contains(&command) {
+        BYTES_TOKEN_POSITION
+    } else {
+        return None;
+    };
+
+    tokens.nth(position - 1).and_then(parse_data_size)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_ITEM_SIZE: usize = 1024 * 1024;
+
+    fn create_decoder() -> FrameDecoder {
+        FrameDecoder::new(
+            Protocol {
+                separator: String::from("--"),
+            },
+            MAX_ITEM_SIZE,
+        )
+    }
+
+    fn create_standard_decoder() -> FrameDecoder {
+        FrameDecoder::new(Protocol::default(), MAX_ITEM_SIZE)
+    }
+
+    fn frame(line: &str, data: Option<&str>) -> Frame {
+        Frame {
+            line: line.to_owned(),
+            data: data.map(|data| Bytes::copy_from_slice(data.as_bytes())),
+        }
+    }
+
+    #[test]
+    fn should_wait_for_the_whole_command_line() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("get te");
+
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+
+        buf.extend_from_slice(b"st--");
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get test", None))));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn should_wait_for_the_whole_data_block() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("set test 0 100 4--ho");
+
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+
+        buf.extend_from_slice(b"la-");
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+
+        buf.extend_from_slice(b"-");
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 4", Some("hola"))))
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn should_decode_pipelined_commands_in_order() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("set test 0 100 4--hola--get test--get other--");
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 4", Some("hola"))))
+        );
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get test", None))));
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get other", None))));
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+    }
+
+    #[test]
+    fn should_take_data_block_by_size_even_if_it_contains_the_separator() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("set test 0 100 6--ho--la--");
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 6", Some("ho--la"))))
+        );
+    }
+
+    #[test]
+    fn should_decode_data_block_bigger_than_a_single_read() {
+        let mut decoder = create_decoder();
+        let value = "a".repeat(5000);
+        let mut buf = BytesMut::from("set test 0 100 5000--");
+
+        for chunk in value.as_bytes().chunks(1024) {
+            assert_eq!(decoder.decode(&mut buf), Ok(None));
+            buf.extend_from_slice(chunk);
+        }
+        buf.extend_from_slice(b"--");
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 5000", Some(&value))))
+        );
+    }
+
+    #[test]
+    fn should_not_wait_for_data_when_size_is_not_a_number() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("set test 0 100 abc--");
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 abc", None)))
+        );
+    }
+
+    #[test]
+    fn should_fail_when_data_block_is_not_followed_by_separator() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("set test 0 100 4--hello--get test--");
+
+        assert_eq!(decoder.decode(&mut buf), Err(ParseError::BadDataChunk));
+    }
+
+    #[test]
+    fn should_resume_with_next_command_after_a_bad_data_chunk() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("set test 0 100 4--hello wor");
+
+        assert_eq!(decoder.decode(&mut buf), Err(ParseError::BadDataChunk));
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+
+        buf.extend_from_slice(b"ld--get test--");
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get test", None))));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn should_not_wait_for_data_when_size_does_not_fit_in_32_bits() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("set test 0 100 18446744073709551615--");
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 18446744073709551615", None)))
+        );
+    }
+
+    #[test]
+    fn should_fail_when_line_is_too_long_and_skip_it() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from("get ");
+        buf.extend_from_slice("a".repeat(MAX_LINE_LENGTH).as_bytes());
+
+        assert_eq!(decoder.decode(&mut buf), Err(ParseError::LineTooLong));
+        assert!(buf.len() < 2);
+
+        buf.extend_from_slice(b"aaa--get test--");
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get test", None))));
+    }
+
+    #[test]
+    fn should_fail_when_line_is_not_valid_utf8() {
+        let mut decoder = create_decoder();
+        let mut buf = BytesMut::from(b"get \xff--get test--".as_slice());
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Err(ParseError::BadCommandLineFormat)
+        );
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get test", None))));
+    }
+
+    #[test]
+    fn should_decode_standard_framing() {
+        let mut decoder = create_standard_decoder();
+        let mut buf = BytesMut::from("set test 0 100 6\r\nho\r\nla\r\nget test\r\n");
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 6", Some("ho\r\nla"))))
+        );
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get test", None))));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn should_accept_lines_ended_by_a_bare_new_line_in_standard_framing() {
+        let mut decoder = create_standard_decoder();
+        let mut buf = BytesMut::from("get test\nget other\r");
+
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get test", None))));
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+
+        buf.extend_from_slice(b"\n");
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(frame("get other", None))));
+    }
+
+    #[test]
+    fn should_fail_when_data_block_is_not_followed_by_standard_terminator() {
+        let mut decoder = create_standard_decoder();
+        let mut buf = BytesMut::from("set test 0 100 4\r\nhola\n");
+
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+
+        buf.extend_from_slice(b"x");
+        assert_eq!(decoder.decode(&mut buf), Err(ParseError::BadDataChunk));
+    }
+
+    #[test]
+    fn should_swallow_data_blocks_bigger_than_the_max_item_size() {
+        let mut decoder = FrameDecoder::new(Protocol::default(), 4);
+        let mut buf = BytesMut::from("set test 0 100 10\r\n0123");
+
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Err(ParseError::ServerError("object too large for cache"))
+        );
+        assert_eq!(decoder.decode(&mut buf), Ok(None));
+        assert!(buf.is_empty());
+
+        buf.extend_from_slice(b"456789\r\nset test 0 100 4\r\nhola\r\n");
+        assert_eq!(
+            decoder.decode(&mut buf),
+            Ok(Some(frame("set test 0 100 4", Some("hola"))))
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{commands::CommandResult, config::Protocol, errors::ParseError};
+
+/**
+ * Turns command results into text protocol responses, every line ended by
+ * the separator in use.
+ */
+pub struct ResponseEncoder {
+    protocol: Protocol,
+}
+
+impl ResponseEncoder {
+    pub fn new(protocol: Protocol) -> ResponseEncoder {
+        ResponseEncoder { protocol }
+    }
+
+    pub fn encode(&self, result: &CommandResult) -> Bytes {
+        let mut message = BytesMut::new();
+
+        match result {
+            CommandResult::Stored { .. } => self.line(&mut message, "STORED"),
+            CommandResult::NotStored => self.line(&mut message, "NOT_STORED"),
+            CommandResult::Exists => self.line(&mut message, "EXISTS"),
+            CommandResult::NotFound => self.line(&mut message, "NOT_FOUND"),
+            CommandResult::Deleted => self.line(&mut message, "DELETED"),
+            CommandResult::Touched => self.line(&mut message, "TOUCHED"),
+            CommandResult::Counter { value, .. } => self.line(&mut message, &value.to_string()),
+            CommandResult::NonNumericValue => self.line(
+                &mut message,
+                "CLIENT_ERROR cannot increment or decrement non-numeric value",
+            ),
+            CommandResult::Values {
+                values,
+                with_cas_unique,
+            } => {
+                for value in values {
+                    let mut line =
+                        format!("VALUE {} {} {}", value.key, value.flags, value.data.len());
+                    if *with_cas_unique {
+                        line.push_str(&format!(" {}", value.cas_unique));
+                    }
+                    self.line(&mut message, &line);
+                    message.put(value.data.as_ref());
+                    message.put(self.protocol.separator.as_bytes());
+                }
+                self.line(&mut message, "END");
+            }
+            CommandResult::Ok => self.line(&mut message, "OK"),
+            CommandResult::Version(version) => {
+                self.line(&mut message, &format!("VERSION {}", version))
+            }
+            CommandResult::Stats(stats) => {
+                for (name, value) in stats {
+                    self.line(&mut message, &format!("STAT {} {}", name, value));
+                }
+                self.line(&mut message, "END");
+            }
+            CommandResult::Reset => self.line(&mut message, "RESET"),
+            CommandResult::OutOfMemory => {
+                self.line(&mut message, "SERVER_ERROR out of memory storing object")
+            }
+            CommandResult::Meta {
+                status,
+                flags,
+                value,
+            } => {
+                let mut line = status.to_string();
+                if let Some(value) = value {
+                    line.push_str(&format!(" {}", value.len()));
+                }
+                for flag in flags {
+                    line.push(' ');
+                    line.push_str(flag);
+                }
+                self.line(&mut message, &line);
+                if let Some(value) = value {
+                    message.put(value.as_ref());
+                    message.put(self.protocol.separator.as_bytes());
+                }
+            }
+            CommandResult::Error(error) => return self.error(error),
+        }
+
+        message.freeze()
+    }
+
+    /**
+     * Lines of a response streamed a part at a time, with no END.
+     */
+    pub fn lines<'a>(&self, lines: impl IntoIterator<Item = &'a str>) -> Bytes {
+        let mut message = BytesMut::new();
+        for line in lines {
+            self.line(&mut message, line);
+        }
+
+        message.freeze()
+    }
+
+    pub fn error(&self, error: &ParseError) -> Bytes {
+        let mut message = BytesMut::new();
+        self.line(&mut message, &error.to_string());
+
+        message.freeze()
+    }
+
+    fn line(&self, message: &mut BytesMut, line: &str) {
+        message.put(line.as_bytes());
+        message.put(self.protocol.separator.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Value;
+
+    #[test]
+    fn should_encode_values_with_cas_unique_only_when_asked() {
+        let encoder = ResponseEncoder::new(Protocol::default());
+        let values = vec![Value {
+            key: "test".to_owned(),
+            flags: 3,
+            cas_unique: 42,
+            data: Bytes::from("hola"),
+        }];
+
+        let result = CommandResult::Values {
+            values: values.clone(),
+            with_cas_unique: false,
+        };
+        assert_eq!(
+            encoder.encode(&result),
+            Bytes::from("VALUE test 3 4\r\nhola\r\nEND\r\n")
+        );
+
+        let result = CommandResult::Values {
+            values,
+            with_cas_unique: true,
+        };
+        assert_eq!(
+            encoder.encode(&result),
+            Bytes::from("VALUE test 3 4 42\r\nhola\r\nEND\r\n")
+        );
+    }
+
+    #[test]
+    fn should_end_lines_with_the_configured_separator() {
+        let encoder = ResponseEncoder::new(Protocol {
+            separator: "--".to_owned(),
+        });
+
+        let result = CommandResult::Stats(vec![("pid".to_owned(), "1".to_owned())]);
+        assert_eq!(encoder.encode(&result), Bytes::from("STAT pid 1--END--"));
+        assert_eq!(
+            encoder.encode(&CommandResult::Error(ParseError::UnknownCommand)),
+            Bytes::from("ERROR--")
+        );
+    }
+
+    #[test]
+    fn should_encode_meta_responses_with_size_of_the_value() {
+        let encoder = ResponseEncoder::new(Protocol::default());
+
+        let result = CommandResult::Meta {
+            status: "VA",
+            flags: vec!["f5".to_owned(), "Oxy".to_owned()],
+            value: Some(Bytes::from("hola")),
+        };
+        assert_eq!(
+            encoder.encode(&result),
+            Bytes::from("VA 4 f5 Oxy\r\nhola\r\n")
+        );
+
+        let result = CommandResult::Meta {
+            status: "HD",
+            flags: vec![],
+            value: None,
+        };
+        assert_eq!(encoder.encode(&result), Bytes::from("HD\r\n"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::str::FromStr;
+
+use crate::errors::ParseError;
+
+/**
+ * Flags of a meta command. Each one is a single character, some of them
+ * followed by an argument, as in `T30` or `Oabc`.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetaFlags {
+    /// b: the key is base64 encoded
+    pub base64_key: bool,
+    /// c: return the CAS unique
+    pub return_cas: bool,
+    /// f: return the client flags
+    pub return_flags: bool,
+    /// h: return whether the item was fetched before
+    pub return_hit: bool,
+    /// k: return the key
+    pub return_key: bool,
+    /// l: return the seconds since the item was last accessed
+    pub return_last_access: bool,
+    /// O: opaque token echoed back
+    pub opaque: Option<String>,
+    /// q: hide the responses a pipeline does not care about
+    pub quiet: bool,
+    /// s: return the size of the value
+    pub return_size: bool,
+    /// t: return the seconds left before the item expires
+    pub return_ttl: bool,
+    /// v: return the value
+    pub return_value: bool,
+    /// C: only act if the CAS unique matches
+    pub compare_cas: Option<u64>,
+    /// F: client flags to store
+    pub client_flags: Option<u16>,
+    /// I: invalidate, marking the item as stale instead of removing it
+    pub invalidate: bool,
+    /// T: new expiration time
+    pub ttl: Option<isize>,
+    /// N: create the item on a miss, with this expiration time
+    pub vivify: Option<isize>,
+    /// J: initial value of a counter created by ma
+    pub initial: Option<u64>,
+    /// D: delta applied by ma
+    pub delta: Option<u64>,
+    /// M: mode of ms and ma
+    pub mode: Option<char>,
+}
+
+impl MetaFlags {
+    pub fn parse<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<MetaFlags, ParseError> {
+        let mut flags = MetaFlags::default();
+
+        for token in tokens {
+            let mut chars = token.chars();
+            let flag = chars.next().ok_or(ParseError::BadCommandLineFormat)?;
+            let argument = chars.as_str();
+
+            match flag {
+                'b' => flags.base64_key = true,
+                'c' => flags.return_cas = true,
+                'f' => flags.return_flags = true,
+                'h' => flags.return_hit = true,
+                'k' => flags.return_key = true,
+                'l' => flags.return_last_access = true,
+                'O' => flags.opaque = Some(argument.to_owned()),
+                'q' => flags.quiet = true,
+                's' => flags.return_size = true,
+                't' => flags.return_ttl = true,
+                'v' => flags.return_value = true,
+                'C' => flags.compare_cas = Some(parse_flag_argument(argument)?),
+                'F' => flags.client_flags = Some(parse_flag_argument(argument)?),
+                'I' => flags.invalidate = true,
+                'T' => flags.ttl = Some(parse_flag_argument(argument)?),
+                'N' => flags.vivify = Some(parse_flag_argument(argument)?),
+                'J' => flags.initial = Some(parse_flag_argument(argument)?),
+                'D' => flags.delta = Some(parse_flag_argument(argument)?),
+                'M' => {
+                    let mut mode = argument.chars();
+                    flags.mode = mode.next();
+                    if flags.mode.is_none() || mode.next().is_some() {
+                        return Err(ParseError::BadCommandLineFormat);
+                    }
+                }
+                _ => return Err(ParseError::BadCommandLineFormat),
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+fn parse_flag_argument<T: FromStr>(argument: &str) -> Result<T, ParseError> {
+    argument
+        .parse()
+        .map_err(|_| ParseError::BadCommandLineFormat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_flags_with_and_without_arguments() {
+        let flags = MetaFlags::parse("v c T30 Oabc q MS F5".split(' ')).unwrap();
+
+        assert!(flags.return_value);
+        assert!(flags.return_cas);
+        assert!(flags.quiet);
+        assert_eq!(flags.ttl, Some(30));
+        assert_eq!(flags.opaque, Some("abc".to_owned()));
+        assert_eq!(flags.mode, Some('S'));
+        assert_eq!(flags.client_flags, Some(5));
+        assert!(!flags.return_key);
+    }
+
+    #[test]
+    fn should_raise_error_on_unknown_flags_or_bad_arguments() {
+        for tokens in ["x", "Tabc", "C-1", "M", "MSE"] {
+            assert_eq!(
+                MetaFlags::parse(tokens.split(' ')),
+                Err(ParseError::BadCommandLineFormat),
+                "{tokens}"
+            );
+        }
+    }
+}
@@ -1,130 +1,409 @@
+mod decoder;
+mod encoder;
+mod meta;
+
+use std::str::FromStr;
+
+use bytes::Bytes;
+
 use crate::{
-    config::Protocol,
-    types::{READ_COMMANDS, WRITE_COMMANDS},
+    errors::ParseError,
+    types::{
+        ADMIN_COMMANDS, ARITHMETIC_COMMANDS, CRAWLER_COMMANDS, DELETE_COMMANDS, FLUSH_COMMANDS,
+        GAT_COMMANDS, MAX_KEY_LENGTH, META_COMMANDS, META_SET_COMMAND, READ_COMMANDS,
+        STATS_COMMANDS, TOUCH_COMMANDS, VERBOSITY_COMMANDS, WRITE_COMMANDS,
+    },
 };
 
-pub struct CommandParserInputDataBuilder {
-    protocol: Protocol,
-}
+pub use self::decoder::{Frame, FrameDecoder};
+pub use self::encoder::ResponseEncoder;
+pub use self::meta::MetaFlags;
 
+const NO_REPLY: &str = "noreply";
+const CAS_COMMAND: &str = "cas";
+
+pub struct CommandParserInputDataBuilder {}
+
+#[derive(Default)]
 pub struct CommandParserInputData {
     pub command: String,
     pub key: String,
-    pub value: Option<String>,
+    /// Every key requested by a retrieval command, `key` being the first one.
+    /// For gat and gats, `key` holds the expiration time instead
+    pub keys: Vec<String>,
+    pub value: Option<Bytes>,
     pub flags: Option<u16>,
     pub value_size_bytes: Option<usize>,
     pub exptime: Option<isize>,
     pub no_reply: Option<bool>,
+    /// Numeric argument of incr and decr
+    pub delta: Option<u64>,
+    /// Level set by verbosity
+    pub verbosity: Option<u32>,
+    /// Sub-command of lru_crawler
+    pub crawler: Option<CrawlerCommand>,
+    pub cas_unique: Option<u64>,
+    pub meta_flags: Option<MetaFlags>,
+}
+
+/**
+ * Sub-commands of lru_crawler.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrawlerCommand {
+    /// Dumps the metadata of the items of a slab class, `None` for all of them
+    Metadump { class: Option<u64> },
+}
+
+impl Default for CommandParserInputDataBuilder {
+    fn default() -> Self {
+        CommandParserInputDataBuilder::new()
+    }
 }
 
 impl CommandParserInputDataBuilder {
-    pub fn new(protocol: Protocol) -> CommandParserInputDataBuilder {
-        CommandParserInputDataBuilder { protocol }
-    }
-
-    pub fn build(&self, data: String) -> Result<CommandParserInputData, String> {
-        let command_and_data_list: Vec<&str> =
-            data.split_terminator(&self.protocol.separator).collect();
-        if command_and_data_list.len() != 1 && command_and_data_list.len() != 2 {
-            tracing::info!(
-                "command_and_data_list is {}, {:?}",
-                command_and_data_list.len(),
-                command_and_data_list
-            );
-            return Err(format!("Wrong number of arguments for {data}"));
-        }
-        let mut command_data = command_and_data_list[0].split_whitespace();
+    pub fn new() -> CommandParserInputDataBuilder {
+        CommandParserInputDataBuilder {}
+    }
+
+    pub fn build(&self, frame: Frame) -> Result<CommandParserInputData, ParseError> {
+        let mut command_data = frame.line.split_whitespace();
         let size = command_data.clone().count();
-        let command = command_data.next().unwrap();
+        let command = match command_data.next() {
+            None => return Err(ParseError::UnknownCommand),
+            Some(command) => command,
+        };
+
+        if FLUSH_COMMANDS.contains(&command) {
+            let (delay, no_reply) = match command_data.collect::<Vec<&str>>()[..] {
+                [] => (0, false),
+                [NO_REPLY] => (0, true),
+                [delay] => (parse_argument(Some(delay))?, false),
+                [delay, NO_REPLY] => (parse_argument(Some(delay))?, true),
+                _ => return Err(ParseError::UnknownCommand),
+            };
+
+            return Ok(CommandParserInputData {
+                command: command.to_owned(),
+                exptime: Some(delay),
+                no_reply: Some(no_reply),
+                ..Default::default()
+            });
+        }
+
+        if ADMIN_COMMANDS.contains(&command) {
+            if size != 1 {
+                return Err(ParseError::UnknownCommand);
+            }
+
+            return Ok(CommandParserInputData {
+                command: command.to_owned(),
+                ..Default::default()
+            });
+        }
+
+        if STATS_COMMANDS.contains(&command) {
+            // the sub-command, if any, is carried as the key
+            let group = match command_data.collect::<Vec<&str>>()[..] {
+                [] => "",
+                [group] => group,
+                _ => return Err(ParseError::UnknownCommand),
+            };
+
+            return Ok(CommandParserInputData {
+                command: command.to_owned(),
+                key: group.to_owned(),
+                ..Default::default()
+            });
+        }
+
+        if CRAWLER_COMMANDS.contains(&command) {
+            let crawler = match command_data.collect::<Vec<&str>>()[..] {
+                ["metadump", "all"] => CrawlerCommand::Metadump { class: None },
+                ["metadump", class] => CrawlerCommand::Metadump {
+                    class: Some(parse_argument(Some(class))?),
+                },
+                _ => return Err(ParseError::UnknownCommand),
+            };
+
+            return Ok(CommandParserInputData {
+                command: command.to_owned(),
+                crawler: Some(crawler),
+                ..Default::default()
+            });
+        }
+
         let key = command_data.next();
         if key.is_none() {
             tracing::info!("key is none");
-            return Err(format!("Wrong number of arguments for {command}"));
+            return Err(ParseError::UnknownCommand);
         }
         let key = key.unwrap();
 
-        if WRITE_COMMANDS.iter().any(|&rc| rc == command) {
-            if command_and_data_list.len() != 2 {
-                tracing::info!("command_and_data_list is {:?}", command_and_data_list);
-                return Err(format!("Wrong number of arguments for {command}"));
-            }
-
-            if size != 5 && size != 6 {
+        if WRITE_COMMANDS.contains(&command) {
+            check_key(key)?;
+            // cas carries one more argument than the other storage commands
+            let expected_size = if command == CAS_COMMAND { 6 } else { 5 };
+            if size != expected_size && size != expected_size + 1 {
                 tracing::info!("size is {}", size);
-                return Err(format!("Wrong number of arguments for {command}"));
+                return Err(ParseError::UnknownCommand);
             }
 
-            let flags: u16 = command_data.next().unwrap().parse().unwrap();
-            let exptime: isize = command_data.next().unwrap().parse().unwrap();
-            let value_size_in_bytes: usize = command_data.next().unwrap().parse().unwrap();
+            let flags: u16 = parse_argument(command_data.next())?;
+            let exptime: isize = parse_argument(command_data.next())?;
+            let value_size_in_bytes = command_data
+                .next()
+                .and_then(decoder::parse_data_size)
+                .ok_or(ParseError::BadCommandLineFormat)?;
+            let cas_unique: Option<u64> = if command == CAS_COMMAND {
+                Some(parse_argument(command_data.next())?)
+            } else {
+                None
+            };
             let no_reply = command_data.next();
-            let value = command_and_data_list[1];
+
+            let value = match frame.data {
+                None => {
+                    tracing::info!("data block is missing for {}", frame.line);
+                    return Err(ParseError::BadDataChunk);
+                }
+                Some(ref value) => value.clone(),
+            };
 
             if value.len() != value_size_in_bytes {
                 tracing::info!("value not matched expected");
-                return Err("Value in bytes does not match expected".to_string());
+                return Err(ParseError::BadDataChunk);
             }
 
             Ok(CommandParserInputData {
                 command: command.to_owned(),
                 key: key.to_owned(),
-                value: Some(value.to_owned()),
+                value: Some(value),
                 flags: Some(flags),
                 value_size_bytes: Some(value_size_in_bytes),
                 exptime: Some(exptime),
                 no_reply: Some(no_reply.is_some()),
+                cas_unique,
+                ..Default::default()
+            })
+        } else if READ_COMMANDS.contains(&command) {
+            let keys: Vec<String> = std::iter::once(key)
+                .chain(command_data)
+                .map(str::to_owned)
+                .collect();
+            keys.iter().try_for_each(|key| check_key(key))?;
+
+            Ok(CommandParserInputData {
+                command: command.to_owned(),
+                key: key.to_owned(),
+                keys,
+                ..Default::default()
+            })
+        } else if DELETE_COMMANDS.contains(&command) {
+            check_key(key)?;
+            // "delete <key> 0" is still accepted for older clients
+            let no_reply = match command_data.collect::<Vec<&str>>()[..] {
+                [] | ["0"] => false,
+                [NO_REPLY] | ["0", NO_REPLY] => true,
+                _ => return Err(ParseError::BadCommandLineFormat),
+            };
+
+            Ok(CommandParserInputData {
+                command: command.to_owned(),
+                key: key.to_owned(),
+                no_reply: Some(no_reply),
+                ..Default::default()
             })
-        } else if READ_COMMANDS.iter().any(|&rc| rc == command) {
-            if command_and_data_list.len() != 1 {
-                return Err(format!("Wrong number of arguments for {command}"));
+        } else if ARITHMETIC_COMMANDS.contains(&command) {
+            if size != 3 && size != 4 {
+                return Err(ParseError::UnknownCommand);
             }
-            if size != 2 {
-                return Err(format!("Wrong number of arguments for {command}"));
+            check_key(key)?;
+
+            let delta: u64 = match command_data.next().unwrap().parse() {
+                Ok(delta) => delta,
+                Err(_) => return Err(ParseError::InvalidNumericDelta),
+            };
+            let no_reply = command_data.next() == Some(NO_REPLY);
+
+            Ok(CommandParserInputData {
+                command: command.to_owned(),
+                key: key.to_owned(),
+                no_reply: Some(no_reply),
+                delta: Some(delta),
+                ..Default::default()
+            })
+        } else if TOUCH_COMMANDS.contains(&command) {
+            if size != 3 && size != 4 {
+                return Err(ParseError::UnknownCommand);
             }
+            check_key(key)?;
+
+            let exptime: isize = parse_argument(command_data.next())?;
+            let no_reply = command_data.next() == Some(NO_REPLY);
 
             Ok(CommandParserInputData {
                 command: command.to_owned(),
                 key: key.to_owned(),
-                value: None,
-                flags: None,
-                value_size_bytes: None,
-                exptime: None,
-                no_reply: None,
+                exptime: Some(exptime),
+                no_reply: Some(no_reply),
+                ..Default::default()
+            })
+        } else if VERBOSITY_COMMANDS.contains(&command) {
+            if size != 2 && size != 3 {
+                return Err(ParseError::UnknownCommand);
+            }
+
+            let verbosity: u32 = parse_argument(Some(key))?;
+            let no_reply = command_data.next() == Some(NO_REPLY);
+
+            Ok(CommandParserInputData {
+                command: command.to_owned(),
+                key: key.to_owned(),
+                no_reply: Some(no_reply),
+                verbosity: Some(verbosity),
+                ..Default::default()
+            })
+        } else if META_COMMANDS.contains(&command) {
+            self.build_meta(command, key, command_data, frame.data)
+        } else if GAT_COMMANDS.contains(&command) {
+            // "gat <exptime> <key>*": the expiration time comes first
+            let exptime: isize = parse_argument(Some(key))?;
+            let keys: Vec<String> = command_data.map(str::to_owned).collect();
+            if keys.is_empty() {
+                return Err(ParseError::UnknownCommand);
+            }
+            keys.iter().try_for_each(|key| check_key(key))?;
+
+            Ok(CommandParserInputData {
+                command: command.to_owned(),
+                key: key.to_owned(),
+                keys,
+                exptime: Some(exptime),
+                ..Default::default()
             })
         } else {
             tracing::info!("Wrong command when parsing command");
-            return Err(String::from("Wrong command"));
+            Err(ParseError::UnknownCommand)
+        }
+    }
+}
+
+impl CommandParserInputDataBuilder {
+    /**
+     * Builds a meta command: `<command> <key> <flags>*`, ms also carrying the
+     * size of its data block right after the key.
+     */
+    fn build_meta<'a>(
+        &self,
+        command: &str,
+        key: &str,
+        mut command_data: impl Iterator<Item = &'a str>,
+        data: Option<Bytes>,
+    ) -> Result<CommandParserInputData, ParseError> {
+        let mut value = None;
+        if command == META_SET_COMMAND {
+            let value_size_in_bytes = command_data
+                .next()
+                .and_then(decoder::parse_data_size)
+                .ok_or(ParseError::BadCommandLineFormat)?;
+            match data {
+                Some(data) if data.len() == value_size_in_bytes => value = Some(data),
+                _ => return Err(ParseError::BadDataChunk),
+            }
+        }
+
+        let flags = MetaFlags::parse(command_data)?;
+        let valid_modes: &[char] = match command {
+            META_SET_COMMAND => &['E', 'A', 'P', 'R', 'S'],
+            "ma" => &['I', '+', 'D', '-'],
+            _ => &[],
+        };
+        if let Some(mode) = flags.mode {
+            if !valid_modes.contains(&mode.to_ascii_uppercase()) {
+                return Err(ParseError::BadCommandLineFormat);
+            }
         }
+
+        let key = if flags.base64_key {
+            base64::decode(key)
+                .ok()
+                .and_then(|key| String::from_utf8(key).ok())
+                .ok_or(ParseError::BadCommandLineFormat)?
+        } else {
+            key.to_owned()
+        };
+        check_key(&key)?;
+
+        Ok(CommandParserInputData {
+            command: command.to_owned(),
+            key,
+            value_size_bytes: value.as_ref().map(Bytes::len),
+            value,
+            no_reply: Some(flags.quiet),
+            meta_flags: Some(flags),
+            ..Default::default()
+        })
     }
 }
 
+/**
+ * Whether `key` follows the memcached rules: at most 250 bytes, without
+ * control characters or whitespace.
+ */
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_KEY_LENGTH
+        && !key.chars().any(|c| c.is_control() || c.is_whitespace())
+}
+
+fn check_key(key: &str) -> Result<(), ParseError> {
+    if !is_valid_key(key) {
+        tracing::info!("invalid key {:?}", key);
+        return Err(ParseError::BadCommandLineFormat);
+    }
+
+    Ok(())
+}
+
+fn parse_argument<T: FromStr>(argument: Option<&str>) -> Result<T, ParseError> {
+    argument
+        .and_then(|argument| argument.parse().ok())
+        .ok_or(ParseError::BadCommandLineFormat)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn create_builder() -> CommandParserInputDataBuilder {
-        CommandParserInputDataBuilder::new(Protocol {
-            separator: String::from("--"),
-        })
+        CommandParserInputDataBuilder::new()
+    }
+
+    fn frame(line: &str, data: Option<&str>) -> Frame {
+        Frame {
+            line: line.to_owned(),
+            data: data.map(|data| Bytes::copy_from_slice(data.as_bytes())),
+        }
     }
 
     #[test]
     fn wrong_command() {
-        let data = String::from("wrong command");
+        let data = frame("wrong command", None);
         let result = create_builder().build(data);
-        assert!(result.is_err());
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
     }
 
     #[test]
     fn wrong_command_when_sending_empty_data() {
-        let data = String::from("");
+        let data = frame("", None);
         let result = create_builder().build(data);
         assert!(result.is_err());
     }
 
     #[test]
     fn should_parse_get_command() {
-        let data = String::from("get test--");
+        let data = frame("get test", None);
         let result = create_builder().build(data);
         assert!(result.is_ok());
         let obj = result.unwrap();
@@ -134,21 +413,25 @@ mod tests {
 
     #[test]
     fn should_raise_wrong_arguments_for_get_command_due_to_missing_arg() {
-        let data = String::from("get--");
+        let data = frame("get", None);
         let result = create_builder().build(data);
         assert!(result.is_err());
     }
 
     #[test]
-    fn should_raise_wrong_arguments_for_get_command_due_to_more_args_than_expected() {
-        let data = String::from("get test lala--");
-        let result = create_builder().build(data);
-        assert!(result.is_err());
+    fn should_parse_get_command_with_several_keys() {
+        for command in ["get", "gets"] {
+            let data = frame(&format!("{command} test lala  other"), None);
+            let obj = create_builder().build(data).unwrap();
+            assert_eq!(obj.command, command);
+            assert_eq!(obj.key, "test");
+            assert_eq!(obj.keys, vec!["test", "lala", "other"]);
+        }
     }
 
     #[test]
     fn should_parse_set_command_with_reply() {
-        let data = String::from("set test 0 100 4--hola--");
+        let data = frame("set test 0 100 4", Some("hola"));
         let result = create_builder().build(data);
         assert!(result.is_ok());
         let obj = result.unwrap();
@@ -157,13 +440,13 @@ mod tests {
         assert_eq!(obj.exptime, Some(100));
         assert_eq!(obj.flags, Some(0));
         assert_eq!(obj.no_reply, Some(false));
-        assert_eq!(obj.value, Some("hola".to_owned()));
+        assert_eq!(obj.value, Some(Bytes::from("hola")));
         assert_eq!(obj.value_size_bytes, Some(4));
     }
 
     #[test]
     fn should_parse_set_command_with_no_reply() {
-        let data = String::from("set test 0 100 4 no_reply--hola--");
+        let data = frame("set test 0 100 4 no_reply", Some("hola"));
         let result = create_builder().build(data);
         assert!(result.is_ok());
         let obj = result.unwrap();
@@ -172,21 +455,387 @@ mod tests {
         assert_eq!(obj.exptime, Some(100));
         assert_eq!(obj.flags, Some(0));
         assert_eq!(obj.no_reply, Some(true));
-        assert_eq!(obj.value, Some("hola".to_owned()));
+        assert_eq!(obj.value, Some(Bytes::from("hola")));
         assert_eq!(obj.value_size_bytes, Some(4));
     }
 
+    #[test]
+    fn should_parse_set_command_with_binary_value() {
+        let value: &[u8] = &[0xff, 0x00, b'\r', b'\n', 0xc3];
+        let data = Frame {
+            line: "set test 0 100 5".to_owned(),
+            data: Some(Bytes::copy_from_slice(value)),
+        };
+        let result = create_builder().build(data);
+        assert!(result.is_ok());
+        let obj = result.unwrap();
+        assert_eq!(obj.value, Some(Bytes::copy_from_slice(value)));
+    }
+
     #[test]
     fn should_raise_error_when_set_command_missing_argument() {
-        let data = String::from("set test 0 100 --hola--");
+        let data = frame("set test 0 100 ", Some("hola"));
         let result = create_builder().build(data);
         assert!(result.is_err());
     }
 
     #[test]
     fn should_raise_error_when_data_passed_to_set_command_is_different_size_than_expected() {
-        let data = String::from("set test 0 100 4--hello--");
+        let data = frame("set test 0 100 4", Some("hello"));
         let result = create_builder().build(data);
-        assert!(result.is_err());
+        assert_eq!(result.err(), Some(ParseError::BadDataChunk));
+    }
+
+    #[test]
+    fn should_raise_client_error_when_set_command_arguments_are_not_numbers() {
+        for line in [
+            "set test abc 100 4",
+            "set test 0 abc 4",
+            "set test 0 100 abc",
+            "set test 0 100 -4",
+            "set test 70000 100 4",
+        ] {
+            let result = create_builder().build(frame(line, Some("hola")));
+            assert_eq!(
+                result.err(),
+                Some(ParseError::BadCommandLineFormat),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_map_errors_to_protocol_responses() {
+        assert_eq!(ParseError::UnknownCommand.to_string(), "ERROR");
+        assert_eq!(
+            ParseError::BadCommandLineFormat.to_string(),
+            "CLIENT_ERROR bad command line format"
+        );
+        assert_eq!(
+            ParseError::BadDataChunk.to_string(),
+            "CLIENT_ERROR bad data chunk"
+        );
+        assert_eq!(
+            ParseError::LineTooLong.to_string(),
+            "CLIENT_ERROR line too long"
+        );
+    }
+
+    #[test]
+    fn should_parse_delete_command() {
+        let result = create_builder().build(frame("delete test", None));
+        let obj = result.unwrap();
+        assert_eq!(obj.command, "delete");
+        assert_eq!(obj.key, "test");
+        assert_eq!(obj.no_reply, Some(false));
+    }
+
+    #[test]
+    fn should_parse_delete_command_with_no_reply() {
+        for line in ["delete test noreply", "delete test 0 noreply"] {
+            let obj = create_builder().build(frame(line, None)).unwrap();
+            assert_eq!(obj.key, "test");
+            assert_eq!(obj.no_reply, Some(true), "{line}");
+        }
+    }
+
+    #[test]
+    fn should_raise_error_when_delete_command_has_unexpected_arguments() {
+        let result = create_builder().build(frame("delete", None));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+
+        for line in [
+            "delete test 10",
+            "delete test lala",
+            "delete test 0 noreply more",
+        ] {
+            let result = create_builder().build(frame(line, None));
+            assert_eq!(
+                result.err(),
+                Some(ParseError::BadCommandLineFormat),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_parse_incr_and_decr_commands() {
+        for command in ["incr", "decr"] {
+            let obj = create_builder()
+                .build(frame(&format!("{command} test 18446744073709551615"), None))
+                .unwrap();
+            assert_eq!(obj.command, command);
+            assert_eq!(obj.key, "test");
+            assert_eq!(obj.delta, Some(u64::MAX));
+            assert_eq!(obj.no_reply, Some(false));
+
+            let obj = create_builder()
+                .build(frame(&format!("{command} test 1 noreply"), None))
+                .unwrap();
+            assert_eq!(obj.delta, Some(1));
+            assert_eq!(obj.no_reply, Some(true));
+        }
+    }
+
+    #[test]
+    fn should_raise_error_when_incr_delta_is_not_an_unsigned_64_bits_number() {
+        for line in [
+            "incr test abc",
+            "incr test -1",
+            "decr test 18446744073709551616",
+        ] {
+            let result = create_builder().build(frame(line, None));
+            assert_eq!(
+                result.err(),
+                Some(ParseError::InvalidNumericDelta),
+                "{line}"
+            );
+        }
+
+        let result = create_builder().build(frame("incr test", None));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+    }
+
+    #[test]
+    fn should_parse_gets_command() {
+        let obj = create_builder().build(frame("gets test", None)).unwrap();
+        assert_eq!(obj.command, "gets");
+        assert_eq!(obj.key, "test");
+    }
+
+    #[test]
+    fn should_parse_cas_command() {
+        let obj = create_builder()
+            .build(frame("cas test 1 100 4 42", Some("hola")))
+            .unwrap();
+        assert_eq!(obj.command, "cas");
+        assert_eq!(obj.key, "test");
+        assert_eq!(obj.flags, Some(1));
+        assert_eq!(obj.exptime, Some(100));
+        assert_eq!(obj.cas_unique, Some(42));
+        assert_eq!(obj.no_reply, Some(false));
+        assert_eq!(obj.value, Some(Bytes::from("hola")));
+
+        let obj = create_builder()
+            .build(frame("cas test 1 100 4 42 noreply", Some("hola")))
+            .unwrap();
+        assert_eq!(obj.no_reply, Some(true));
+    }
+
+    #[test]
+    fn should_raise_error_when_cas_unique_is_missing_or_invalid() {
+        let result = create_builder().build(frame("cas test 1 100 4", Some("hola")));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+
+        let result = create_builder().build(frame("cas test 1 100 4 abc", Some("hola")));
+        assert_eq!(result.err(), Some(ParseError::BadCommandLineFormat));
+    }
+
+    #[test]
+    fn should_parse_touch_command() {
+        let obj = create_builder()
+            .build(frame("touch test 100", None))
+            .unwrap();
+        assert_eq!(obj.command, "touch");
+        assert_eq!(obj.key, "test");
+        assert_eq!(obj.exptime, Some(100));
+        assert_eq!(obj.no_reply, Some(false));
+
+        let obj = create_builder()
+            .build(frame("touch test -1 noreply", None))
+            .unwrap();
+        assert_eq!(obj.exptime, Some(-1));
+        assert_eq!(obj.no_reply, Some(true));
+
+        let result = create_builder().build(frame("touch test abc", None));
+        assert_eq!(result.err(), Some(ParseError::BadCommandLineFormat));
+
+        let result = create_builder().build(frame("touch test", None));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+    }
+
+    #[test]
+    fn should_parse_gat_and_gats_commands() {
+        for command in ["gat", "gats"] {
+            let obj = create_builder()
+                .build(frame(&format!("{command} 100 test other"), None))
+                .unwrap();
+            assert_eq!(obj.command, command);
+            assert_eq!(obj.exptime, Some(100));
+            assert_eq!(obj.keys, vec!["test", "other"]);
+
+            let result = create_builder().build(frame(&format!("{command} 100"), None));
+            assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+
+            let result = create_builder().build(frame(&format!("{command} test"), None));
+            assert_eq!(result.err(), Some(ParseError::BadCommandLineFormat));
+        }
+    }
+
+    #[test]
+    fn should_parse_flush_all_command() {
+        for (line, delay, no_reply) in [
+            ("flush_all", 0, false),
+            ("flush_all noreply", 0, true),
+            ("flush_all 10", 10, false),
+            ("flush_all 10 noreply", 10, true),
+        ] {
+            let obj = create_builder().build(frame(line, None)).unwrap();
+            assert_eq!(obj.command, "flush_all");
+            assert_eq!(obj.exptime, Some(delay), "{line}");
+            assert_eq!(obj.no_reply, Some(no_reply), "{line}");
+        }
+
+        let result = create_builder().build(frame("flush_all abc", None));
+        assert_eq!(result.err(), Some(ParseError::BadCommandLineFormat));
+
+        let result = create_builder().build(frame("flush_all 10 noreply more", None));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+    }
+
+    #[test]
+    fn should_parse_stats_command() {
+        let obj = create_builder().build(frame("stats", None)).unwrap();
+        assert_eq!(obj.command, "stats");
+        assert_eq!(obj.key, "");
+
+        let obj = create_builder().build(frame("stats items", None)).unwrap();
+        assert_eq!(obj.key, "items");
+
+        let result = create_builder().build(frame("stats items more", None));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+    }
+
+    #[test]
+    fn should_parse_lru_crawler_metadump_command() {
+        let obj = create_builder()
+            .build(frame("lru_crawler metadump all", None))
+            .unwrap();
+        assert_eq!(obj.command, "lru_crawler");
+        assert_eq!(obj.crawler, Some(CrawlerCommand::Metadump { class: None }));
+        assert_eq!(obj.delta, None);
+
+        let obj = create_builder()
+            .build(frame("lru_crawler metadump 3", None))
+            .unwrap();
+        assert_eq!(
+            obj.crawler,
+            Some(CrawlerCommand::Metadump { class: Some(3) })
+        );
+
+        let result = create_builder().build(frame("lru_crawler metadump some", None));
+        assert_eq!(result.err(), Some(ParseError::BadCommandLineFormat));
+
+        let result = create_builder().build(frame("lru_crawler crawl all", None));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+    }
+
+    #[test]
+    fn should_parse_admin_commands() {
+        for command in ["version", "quit"] {
+            let obj = create_builder().build(frame(command, None)).unwrap();
+            assert_eq!(obj.command, command);
+
+            let result = create_builder().build(frame(&format!("{command} more"), None));
+            assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+        }
+    }
+
+    #[test]
+    fn should_parse_verbosity_command() {
+        let obj = create_builder().build(frame("verbosity 2", None)).unwrap();
+        assert_eq!(obj.command, "verbosity");
+        assert_eq!(obj.verbosity, Some(2));
+        assert_eq!(obj.no_reply, Some(false));
+
+        let obj = create_builder()
+            .build(frame("verbosity 0 noreply", None))
+            .unwrap();
+        assert_eq!(obj.verbosity, Some(0));
+        assert_eq!(obj.no_reply, Some(true));
+
+        let result = create_builder().build(frame("verbosity", None));
+        assert_eq!(result.err(), Some(ParseError::UnknownCommand));
+
+        for line in ["verbosity loud", "verbosity -1", "verbosity 4294967296"] {
+            let result = create_builder().build(frame(line, None));
+            assert_eq!(
+                result.err(),
+                Some(ParseError::BadCommandLineFormat),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_parse_meta_commands() {
+        let obj = create_builder()
+            .build(frame("mg test v c T30", None))
+            .unwrap();
+        assert_eq!(obj.command, "mg");
+        assert_eq!(obj.key, "test");
+        let flags = obj.meta_flags.unwrap();
+        assert!(flags.return_value && flags.return_cas);
+        assert_eq!(flags.ttl, Some(30));
+
+        let obj = create_builder()
+            .build(frame("ms dGVzdA== 4 b MA q", Some("hola")))
+            .unwrap();
+        assert_eq!(obj.key, "test");
+        assert_eq!(obj.value, Some(Bytes::from("hola")));
+        assert_eq!(obj.no_reply, Some(true));
+        assert_eq!(obj.meta_flags.unwrap().mode, Some('A'));
+
+        let obj = create_builder().build(frame("mn", None)).unwrap();
+        assert_eq!(obj.command, "mn");
+    }
+
+    #[test]
+    fn should_raise_error_on_invalid_meta_commands() {
+        for (line, data, error) in [
+            ("ms test 4", Some("hello"), ParseError::BadDataChunk),
+            (
+                "ms test abc",
+                Some("hola"),
+                ParseError::BadCommandLineFormat,
+            ),
+            (
+                "ms test 4 MI",
+                Some("hola"),
+                ParseError::BadCommandLineFormat,
+            ),
+            ("ma test MS", None, ParseError::BadCommandLineFormat),
+            ("mg test x", None, ParseError::BadCommandLineFormat),
+            ("mg !!! b", None, ParseError::BadCommandLineFormat),
+            ("mg", None, ParseError::UnknownCommand),
+        ] {
+            let result = create_builder().build(frame(line, data));
+            assert_eq!(result.err(), Some(error), "{line}");
+        }
+    }
+
+    #[test]
+    fn should_raise_error_on_invalid_keys() {
+        let long_key = "a".repeat(MAX_KEY_LENGTH + 1);
+        for line in [
+            format!("set {} 0 0 4", long_key),
+            format!("get test {}", long_key),
+            "delete te\u{1}st".to_owned(),
+            "incr te\u{7f}st 1".to_owned(),
+            "gat 10 test te\u{1b}st".to_owned(),
+        ] {
+            let result = create_builder().build(frame(&line, Some("hola")));
+            assert_eq!(
+                result.err(),
+                Some(ParseError::BadCommandLineFormat),
+                "{line}"
+            );
+        }
+
+        let key = "a".repeat(MAX_KEY_LENGTH);
+        let obj = create_builder()
+            .build(frame(&format!("get {}", key), None))
+            .unwrap();
+        assert_eq!(obj.key, key);
     }
 }
@@ -0,0 +1,234 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn incr(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn reset(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+}
+
+/**
+ * Declares the counters cleared by `stats reset`, reported by `stats` in
+ * declaration order.
+ */
+macro_rules! counters {
+    ($($name:ident),* $(,)?) => {
+        #[derive(Debug, Default)]
+        pub struct Counters {
+            $(pub $name: Counter,)*
+        }
+
+        impl Counters {
+            pub fn values(&self) -> Vec<(&'static str, u64)> {
+                vec![$((stringify!($name), self.$name.get()),)*]
+            }
+
+            fn reset(&self) {
+                $(self.$name.reset();)*
+            }
+        }
+    };
+}
+
+counters!(
+    total_connections,
+    cmd_get,
+    cmd_set,
+    cmd_flush,
+    cmd_touch,
+    get_hits,
+    get_misses,
+    get_expired,
+    delete_misses,
+    delete_hits,
+    incr_misses,
+    incr_hits,
+    decr_misses,
+    decr_hits,
+    cas_misses,
+    cas_hits,
+    cas_badval,
+    touch_hits,
+    touch_misses,
+    bytes_read,
+    bytes_written,
+    total_items,
+    expired_unfetched,
+    evictions,
+    reclaimed,
+    crawler_reclaimed,
+    crawler_items_checked,
+    lru_crawler_starts,
+);
+
+#[derive(Debug)]
+pub struct ConnectionStats {
+    pub id: u64,
+    pub addr: SocketAddr,
+    pub secs_since_last_cmd: u64,
+}
+
+/**
+ * Registration of an open connection, through which it reports its commands
+ * without locking the list of every connection.
+ */
+#[derive(Debug)]
+pub struct ConnectionActivity {
+    pub id: u64,
+    /// Milliseconds from the start of the server to the last command
+    last_command_at: Arc<AtomicU64>,
+}
+
+/**
+ * Server wide statistics, shared by every connection.
+ */
+#[derive(Debug)]
+pub struct Stats {
+    started_at: Instant,
+    pub counters: Counters,
+    last_connection_id: AtomicU64,
+    connections: Mutex<HashMap<u64, (SocketAddr, Arc<AtomicU64>)>>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            started_at: Instant::now(),
+            counters: Counters::default(),
+            last_connection_id: AtomicU64::new(0),
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn uptime(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+
+    /**
+     * Registers a new connection, identified in `stats conns` by the id of
+     * the registration returned.
+     */
+    pub fn connection_opened(&self, addr: SocketAddr) -> ConnectionActivity {
+        let id = self.last_connection_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let last_command_at = Arc::new(AtomicU64::new(self.millis_since_start()));
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(id, (addr, last_command_at.clone()));
+        self.counters.total_connections.incr();
+
+        ConnectionActivity {
+            id,
+            last_command_at,
+        }
+    }
+
+    pub fn connection_closed(&self, connection: &ConnectionActivity) {
+        self.connections.lock().unwrap().remove(&connection.id);
+    }
+
+    /**
+     * Called for every command, so it takes no lock.
+     */
+    pub fn command_received(&self, connection: &ConnectionActivity) {
+        connection
+            .last_command_at
+            .store(self.millis_since_start(), Ordering::Relaxed);
+    }
+
+    pub fn curr_connections(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    pub fn connections(&self) -> Vec<ConnectionStats> {
+        let now = self.millis_since_start();
+        let mut connections: Vec<ConnectionStats> = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, (addr, last_command_at))| ConnectionStats {
+                id: *id,
+                addr: *addr,
+                secs_since_last_cmd: now.saturating_sub(last_command_at.load(Ordering::Relaxed))
+                    / 1000,
+            })
+            .collect();
+        connections.sort_by_key(|connection| connection.id);
+
+        connections
+    }
+
+    fn millis_since_start(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+
+    /**
+     * Clears the counters. Gauges like the number of open connections are
+     * left untouched.
+     */
+    pub fn reset(&self) {
+        self.counters.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_reset_counters_but_not_connections() {
+        let stats = Stats::new();
+        let connection = stats.connection_opened(SocketAddr::from(([127, 0, 0, 1], 1234)));
+        stats.counters.get_hits.add(3);
+
+        stats.reset();
+
+        assert_eq!(stats.counters.get_hits.get(), 0);
+        assert_eq!(stats.counters.total_connections.get(), 0);
+        assert_eq!(stats.curr_connections(), 1);
+        stats.command_received(&connection);
+        assert_eq!(stats.connections()[0].secs_since_last_cmd, 0);
+
+        stats.connection_closed(&connection);
+        assert_eq!(stats.curr_connections(), 0);
+    }
+
+    #[test]
+    fn should_report_counters_in_declaration_order() {
+        let stats = Stats::new();
+        stats.counters.cmd_get.incr();
+
+        let values = stats.counters.values();
+        assert_eq!(values[0], ("total_connections", 0));
+        assert_eq!(values[1], ("cmd_get", 1));
+    }
+}
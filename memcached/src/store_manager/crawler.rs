@@ -0,0 +1,43 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{stats::Stats, types::Store};
+
+/// Items checked at most while holding the lock of a shard
+const CRAWL_BATCH: usize = 100;
+/// Pause between batches, which bounds the items checked per second
+const BATCH_PAUSE: Duration = Duration::from_millis(1);
+/// Pause between a crawl over every shard and the next one
+const CRAWL_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+ * Background task reclaiming the expired items nobody asks for, which
+ * commands would otherwise only find and remove when fetching them. It goes
+ * over every shard again and again, a batch of items at a time, so clients
+ * never wait long for the lock of a shard being crawled.
+ */
+pub async fn run(store: Store, stats: Arc<Stats>) {
+    loop {
+        tokio::time::sleep(CRAWL_INTERVAL).await;
+        stats.counters.lru_crawler_starts.incr();
+
+        for shard in 0..store.shard_count() {
+            let mut cursor = usize::MAX;
+            while cursor > 0 {
+                let (checked, reclaimed) = {
+                    let mut shard = store.lock_shard(shard);
+                    let start = cursor.min(shard.len());
+                    let reclaimed = shard.reap(&mut cursor, CRAWL_BATCH);
+                    (start - cursor, reclaimed)
+                };
+
+                stats.counters.crawler_items_checked.add(checked as u64);
+                stats.counters.reclaimed.add(reclaimed.len() as u64);
+                stats.counters.crawler_reclaimed.add(reclaimed.len() as u64);
+                let unfetched = reclaimed.iter().filter(|item| !item.fetched).count();
+                stats.counters.expired_unfetched.add(unfetched as u64);
+
+                tokio::time::sleep(BATCH_PAUSE).await;
+            }
+        }
+    }
+}
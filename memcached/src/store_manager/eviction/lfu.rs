@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::EvictionPolicy;
+use crate::{item::Item, store_manager::lru::Lru};
+
+#[derive(Debug)]
+struct Position {
+    /// Times the item was used, counting its insertion
+    frequency: u64,
+    /// Index of the key in the bucket of its frequency
+    node: usize,
+}
+
+/**
+ * Evicts the least frequently used item, the least recently used one among
+ * those used as few times. Keys are kept in one bucket per frequency, so a
+ * use only moves a key to the next bucket.
+ */
+#[derive(Debug, Default)]
+pub struct Lfu {
+    positions: HashMap<String, Position>,
+    buckets: BTreeMap<u64, Lru>,
+}
+
+impl Lfu {
+    fn link(&mut self, key: &str, frequency: u64) {
+        let node = self
+            .buckets
+            .entry(frequency)
+            .or_default()
+            .push_front(key.to_owned());
+        self.positions
+            .insert(key.to_owned(), Position { frequency, node });
+    }
+
+    fn unlink(&mut self, key: &str) -> Option<u64> {
+        let position = self.positions.remove(key)?;
+        let bucket = self.buckets.get_mut(&position.frequency).unwrap();
+        bucket.remove(position.node);
+        if bucket.len() == 0 {
+            self.buckets.remove(&position.frequency);
+        }
+
+        Some(position.frequency)
+    }
+}
+
+impl EvictionPolicy for Lfu {
+    fn insert(&mut self, key: &str, _: &Item) {
+        self.link(key, 1);
+    }
+
+    fn access(&mut self, key: &str) {
+        if let Some(frequency) = self.unlink(key) {
+            self.link(key, frequency.saturating_add(1));
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.unlink(key);
+    }
+
+    fn victim(&mut self) -> Option<String> {
+        let (_, bucket) = self.buckets.first_key_value()?;
+
+        bucket.back().map(str::to_owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::item::tests::ItemBuilder;
+
+    use super::*;
+
+    #[test]
+    fn should_evict_the_least_frequently_used_item() {
+        let mut lfu = Lfu::default();
+        for key in ["a", "b", "c"] {
+            lfu.insert(key, &ItemBuilder::new().build());
+        }
+        lfu.access("a");
+        lfu.access("a");
+        lfu.access("b");
+
+        assert_eq!(lfu.victim().as_deref(), Some("c"));
+        lfu.remove("c");
+        assert_eq!(lfu.victim().as_deref(), Some("b"));
+
+        // the least recently used one among the same frequency
+        lfu.access("b");
+        lfu.insert("d", &ItemBuilder::new().build());
+        lfu.access("d");
+        lfu.access("d");
+        assert_eq!(lfu.victim().as_deref(), Some("a"));
+
+        for key in ["a", "b", "d"] {
+            lfu.remove(key);
+        }
+        assert!(lfu.victim().is_none());
+        assert!(lfu.buckets.is_empty());
+    }
+}
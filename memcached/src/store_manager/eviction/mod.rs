@@ -0,0 +1,65 @@
+mod lfu;
+mod random;
+mod segmented_lru;
+mod tiny_lfu;
+
+use std::fmt;
+
+use crate::{config::Eviction, item::Item};
+
+pub use self::segmented_lru::{HOT_LRU_PCT, TEMPORARY_TTL, WARM_LRU_PCT};
+
+/**
+ * Decides which item of a slab class is evicted when the class needs a chunk
+ * and no page is left. Every class has its own policy, told about the keys
+ * stored, fetched and removed in it.
+ */
+pub trait EvictionPolicy: fmt::Debug + Send {
+    /**
+     * The item was stored under `key`, which is not tracked yet.
+     */
+    fn insert(&mut self, key: &str, item: &Item);
+
+    /**
+     * The item stored under `key` was used.
+     */
+    fn access(&mut self, key: &str);
+
+    /**
+     * The item stored under `key` is gone, evicted or not.
+     */
+    fn remove(&mut self, key: &str);
+
+    /**
+     * Key of the next item to evict, `None` if no key is tracked. It is
+     * removed through `remove` once the store drops the item.
+     */
+    fn victim(&mut self) -> Option<String>;
+
+    /**
+     * Work done in the background by the LRU maintainer, returning how many
+     * items it moved around.
+     */
+    fn maintain(&mut self) -> usize {
+        0
+    }
+
+    /**
+     * Counters of the policy, reported by `stats items` for the class.
+     */
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![]
+    }
+}
+
+/**
+ * Policy of a new slab class.
+ */
+pub fn create(eviction: Eviction) -> Box<dyn EvictionPolicy> {
+    match eviction {
+        Eviction::Lru => Box::<segmented_lru::SegmentedLru>::default(),
+        Eviction::Lfu => Box::<lfu::Lfu>::default(),
+        Eviction::WTinyLfu => Box::<tiny_lfu::WTinyLfu>::default(),
+        Eviction::Random => Box::<random::RandomEviction>::default(),
+    }
+}
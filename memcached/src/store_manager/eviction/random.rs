@@ -0,0 +1,97 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+};
+
+use super::EvictionPolicy;
+use crate::item::Item;
+
+/**
+ * Evicts any item, picked at random. It keeps no order at all, so storing
+ * and fetching items costs next to nothing.
+ */
+#[derive(Debug)]
+pub struct RandomEviction {
+    keys: Vec<String>,
+    /// Index of every key in `keys`
+    indexes: HashMap<String, usize>,
+    /// xorshift64 state
+    state: u64,
+}
+
+impl Default for RandomEviction {
+    fn default() -> Self {
+        RandomEviction {
+            keys: vec![],
+            indexes: HashMap::new(),
+            // any seed but zero, which xorshift never leaves
+            state: RandomState::new().hash_one("seed") | 1,
+        }
+    }
+}
+
+impl RandomEviction {
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl EvictionPolicy for RandomEviction {
+    fn insert(&mut self, key: &str, _: &Item) {
+        self.indexes.insert(key.to_owned(), self.keys.len());
+        self.keys.push(key.to_owned());
+    }
+
+    fn access(&mut self, _: &str) {}
+
+    fn remove(&mut self, key: &str) {
+        let index = match self.indexes.remove(key) {
+            None => return,
+            Some(index) => index,
+        };
+        self.keys.swap_remove(index);
+        // the last key took the place of the removed one
+        if let Some(moved) = self.keys.get(index) {
+            self.indexes.insert(moved.clone(), index);
+        }
+    }
+
+    fn victim(&mut self) -> Option<String> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let index = self.next() % self.keys.len() as u64;
+
+        Some(self.keys[index as usize].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::item::tests::ItemBuilder;
+
+    use super::*;
+
+    #[test]
+    fn should_evict_any_of_the_keys_tracked() {
+        let mut random = RandomEviction::default();
+        for key in ["a", "b", "c", "d"] {
+            random.insert(key, &ItemBuilder::new().build());
+        }
+        random.remove("b");
+        random.remove("b");
+
+        let victims: HashSet<String> = (0..200).filter_map(|_| random.victim()).collect();
+        assert_eq!(victims, HashSet::from(["a", "c", "d"].map(String::from)));
+
+        for key in ["a", "c", "d"] {
+            random.remove(key);
+        }
+        assert!(random.victim().is_none());
+    }
+}
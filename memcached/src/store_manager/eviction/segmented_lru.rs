@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use super::EvictionPolicy;
+use crate::{item::Item, store_manager::lru::Lru};
+
+/// Share of the items of a class kept in hot, the rest going to warm or cold
+pub const HOT_LRU_PCT: usize = 20;
+/// Share of the items of a class kept in warm
+pub const WARM_LRU_PCT: usize = 40;
+/// Items expiring in this many seconds or less go to temp instead of hot
+pub const TEMPORARY_TTL: i64 = 61;
+/// Seconds an item is not relinked for after being fetched
+const BUMP_INTERVAL: i64 = 60;
+/// Moves the maintainer makes in a class in one pass at most
+const MAX_JUGGLES: usize = 500;
+/// Active items moved out of the way before one is evicted anyway
+const MAX_EVICTION_RESCUES: usize = 5;
+/// Fetched cold items waiting for the maintainer, any other is not queued
+const MAX_BUMPED: usize = 1024;
+
+/**
+ * Segments the items of a slab class are split in. New items start in hot,
+ * or in temp when they expire soon, and the maintainer moves them down to
+ * warm if they were fetched in the meantime or to cold otherwise, so a burst
+ * of keys read once only ever pushes other cold items out.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Hot,
+    Warm,
+    Cold,
+    Temp,
+}
+
+#[derive(Debug)]
+struct Position {
+    segment: Segment,
+    /// Index of the key in the LRU of its segment
+    node: usize,
+    /// Fetched since it got to its segment
+    active: bool,
+    /// Unix time it was last relinked because of a fetch
+    last_bump: i64,
+}
+
+/**
+ * memcached's LRU: evicts the least recently used items, from cold first,
+ * while the maintainer keeps hot and warm under their share of the items.
+ */
+#[derive(Debug)]
+pub struct SegmentedLru {
+    positions: HashMap<String, Position>,
+    segments: [Lru; 4],
+    bump_interval: i64,
+    moves_to_cold: u64,
+    moves_to_warm: u64,
+    /// Warm items fetched again, moved back to the head of warm
+    moves_within_lru: u64,
+    /// Cold items fetched, for the maintainer to move to warm
+    bumped: Vec<String>,
+}
+
+impl Default for SegmentedLru {
+    fn default() -> Self {
+        SegmentedLru {
+            positions: HashMap::new(),
+            segments: Default::default(),
+            bump_interval: BUMP_INTERVAL,
+            moves_to_cold: 0,
+            moves_to_warm: 0,
+            moves_within_lru: 0,
+            bumped: vec![],
+        }
+    }
+}
+
+impl SegmentedLru {
+    fn segment(&self, segment: Segment) -> &Lru {
+        &self.segments[segment as usize]
+    }
+
+    /**
+     * Moves the item to the head of `segment`, which may be its own, as an
+     * inactive item.
+     */
+    fn relink(&mut self, key: &str, segment: Segment) {
+        let position = self.positions.get_mut(key).unwrap();
+        self.segments[position.segment as usize].remove(position.node);
+        position.node = self.segments[segment as usize].push_front(key.to_owned());
+        position.segment = segment;
+        position.active = false;
+    }
+
+    /**
+     * Moves tails of `segment` down to cold, or to warm if they were
+     * fetched, until it is back under `limit`.
+     */
+    fn shrink(&mut self, segment: Segment, limit: usize, juggles: &mut usize) {
+        while *juggles < MAX_JUGGLES && self.segment(segment).len() > limit {
+            let key = self.segment(segment).back().unwrap().to_owned();
+            let destination = match self.positions[&key].active {
+                true => Segment::Warm,
+                false => Segment::Cold,
+            };
+            self.relink(&key, destination);
+
+            match (destination, segment) {
+                (Segment::Cold, _) => self.moves_to_cold += 1,
+                (_, Segment::Warm) => self.moves_within_lru += 1,
+                _ => self.moves_to_warm += 1,
+            }
+            *juggles += 1;
+        }
+    }
+}
+
+impl EvictionPolicy for SegmentedLru {
+    fn insert(&mut self, key: &str, item: &Item) {
+        let segment = match item.ttl() {
+            0..=TEMPORARY_TTL => Segment::Temp,
+            _ => Segment::Hot,
+        };
+        let node = self.segments[segment as usize].push_front(key.to_owned());
+        self.positions.insert(
+            key.to_owned(),
+            Position {
+                segment,
+                node,
+                active: false,
+                last_bump: Utc::now().timestamp(),
+            },
+        );
+    }
+
+    /**
+     * Marks the item as active. Unless it was already bumped recently, a
+     * warm item goes back to the head of warm and a cold one is queued for
+     * the maintainer to move it to warm.
+     */
+    fn access(&mut self, key: &str) {
+        let position = match self.positions.get_mut(key) {
+            None => return,
+            Some(position) => position,
+        };
+        position.active = true;
+
+        let now = Utc::now().timestamp();
+        if now - position.last_bump < self.bump_interval {
+            return;
+        }
+        position.last_bump = now;
+        match position.segment {
+            Segment::Warm => self.segments[Segment::Warm as usize].touch(position.node),
+            Segment::Cold if self.bumped.len() < MAX_BUMPED => self.bumped.push(key.to_owned()),
+            _ => {}
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(position) = self.positions.remove(key) {
+            self.segments[position.segment as usize].remove(position.node);
+        }
+    }
+
+    /**
+     * Least recently used item, looking in cold, hot, warm and temp in that
+     * order. A few active items found on the way are saved, moved to warm
+     * instead.
+     */
+    fn victim(&mut self) -> Option<String> {
+        let mut rescues = 0;
+
+        for segment in [Segment::Cold, Segment::Hot, Segment::Warm, Segment::Temp] {
+            while let Some(key) = self.segment(segment).back() {
+                let key = key.to_owned();
+                if !self.positions[&key].active
+                    || segment == Segment::Temp
+                    || rescues == MAX_EVICTION_RESCUES
+                {
+                    return Some(key);
+                }
+
+                rescues += 1;
+                self.relink(&key, Segment::Warm);
+                self.moves_to_warm += 1;
+            }
+        }
+
+        None
+    }
+
+    /**
+     * Fetched cold items go to warm, then hot and warm are brought back under
+     * their share of the items.
+     */
+    fn maintain(&mut self) -> usize {
+        let mut juggles = 0;
+
+        for key in std::mem::take(&mut self.bumped) {
+            // it may be gone or have been moved since it was fetched
+            let bumped = self
+                .positions
+                .get(&key)
+                .is_some_and(|position| position.segment == Segment::Cold && position.active);
+            if bumped {
+                self.relink(&key, Segment::Warm);
+                self.moves_to_warm += 1;
+                juggles += 1;
+            }
+        }
+
+        let items = self.positions.len() - self.segment(Segment::Temp).len();
+        self.shrink(Segment::Hot, items * HOT_LRU_PCT / 100, &mut juggles);
+        self.shrink(Segment::Warm, items * WARM_LRU_PCT / 100, &mut juggles);
+
+        juggles
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("number_hot", self.segment(Segment::Hot).len() as u64),
+            ("number_warm", self.segment(Segment::Warm).len() as u64),
+            ("number_cold", self.segment(Segment::Cold).len() as u64),
+            ("number_temp", self.segment(Segment::Temp).len() as u64),
+            ("moves_to_cold", self.moves_to_cold),
+            ("moves_to_warm", self.moves_to_warm),
+            ("moves_within_lru", self.moves_within_lru),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::item::tests::ItemBuilder;
+
+    use super::*;
+
+    fn create_lru(keys: &[&str]) -> SegmentedLru {
+        let mut lru = SegmentedLru::default();
+        for key in keys {
+            lru.insert(key, &ItemBuilder::new().build());
+        }
+
+        lru
+    }
+
+    fn segment(lru: &SegmentedLru, key: &str) -> Segment {
+        lru.positions[key].segment
+    }
+
+    #[test]
+    fn should_move_items_down_the_segments_in_the_background() {
+        let mut lru = create_lru(&["key0", "key1", "key2", "key3", "key4"]);
+        assert_eq!(segment(&lru, "key0"), Segment::Hot);
+
+        lru.access("key0");
+        // hot keeps 1 of the 5 items, the fetched one goes to warm
+        assert_eq!(lru.maintain(), 4);
+        assert_eq!(segment(&lru, "key0"), Segment::Warm);
+        for key in ["key1", "key2", "key3"] {
+            assert_eq!(segment(&lru, key), Segment::Cold);
+        }
+        assert_eq!(segment(&lru, "key4"), Segment::Hot);
+        assert_eq!((lru.moves_to_warm, lru.moves_to_cold), (1, 3));
+
+        // settled until something is fetched again
+        assert_eq!(lru.maintain(), 0);
+    }
+
+    #[test]
+    fn should_move_fetched_cold_items_to_warm_at_most_once_per_interval() {
+        let mut lru = create_lru(&["key0", "key1", "key2", "key3", "key4"]);
+        lru.maintain();
+
+        // fetched right after being stored, so not bumped
+        lru.access("key1");
+        assert!(lru.bumped.is_empty());
+
+        lru.bump_interval = 0;
+        lru.access("key2");
+        assert_eq!(segment(&lru, "key2"), Segment::Cold);
+        lru.maintain();
+        assert_eq!(segment(&lru, "key2"), Segment::Warm);
+        assert_eq!(segment(&lru, "key1"), Segment::Cold);
+    }
+
+    #[test]
+    fn should_evict_from_cold_first_saving_fetched_items() {
+        let mut lru = create_lru(&["key0", "key1", "key2", "key3", "key4"]);
+        lru.maintain();
+        // cold from head to tail: key3, key2, key1, key0
+        lru.access("key0");
+
+        assert_eq!(lru.victim().as_deref(), Some("key1"));
+        assert_eq!(segment(&lru, "key0"), Segment::Warm);
+
+        lru.remove("key1");
+        assert!(!lru.positions.contains_key("key1"));
+        assert_eq!(lru.victim().as_deref(), Some("key2"));
+    }
+
+    #[test]
+    fn should_keep_items_expiring_soon_in_temp() {
+        let mut lru = create_lru(&["key0"]);
+        let mut item = ItemBuilder::new().build();
+        item.touch(TEMPORARY_TTL as isize);
+        lru.insert("temp", &item);
+
+        lru.access("temp");
+        lru.maintain();
+        assert_eq!(segment(&lru, "temp"), Segment::Temp);
+        assert_eq!(segment(&lru, "key0"), Segment::Cold);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{BuildHasher, BuildHasherDefault},
+};
+
+use super::EvictionPolicy;
+use crate::{item::Item, store_manager::lru::Lru};
+
+/// Counters in every row of the sketch, a power of two
+const SKETCH_WIDTH: usize = 4096;
+const SKETCH_DEPTH: usize = 4;
+/// Counters saturate at this value, as 4 bit counters would
+const MAX_FREQUENCY: u8 = 15;
+/// Uses counted before every counter is halved, so old uses fade away
+const SAMPLE_SIZE: usize = 10 * SKETCH_WIDTH;
+/// Share of the items kept in the window, at least one
+const WINDOW_PCT: usize = 1;
+/// Share of the rest of the items kept in the protected segment
+const PROTECTED_PCT: usize = 80;
+
+/**
+ * Count-min sketch estimating how many times each key was used recently,
+ * in a fixed amount of memory whatever the number of keys.
+ */
+#[derive(Debug)]
+struct FrequencySketch {
+    counters: Vec<u8>,
+    additions: usize,
+}
+
+impl FrequencySketch {
+    fn new() -> FrequencySketch {
+        FrequencySketch {
+            counters: vec![0; SKETCH_WIDTH * SKETCH_DEPTH],
+            additions: 0,
+        }
+    }
+
+    /**
+     * Index of the counter of `key` in every row, by double hashing.
+     */
+    fn indexes(key: &str) -> impl Iterator<Item = usize> {
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(key);
+        let (first, second) = (hash as usize, (hash >> 32) as usize | 1);
+
+        (0..SKETCH_DEPTH).map(move |row| {
+            row * SKETCH_WIDTH + (first.wrapping_add(row.wrapping_mul(second)) & (SKETCH_WIDTH - 1))
+        })
+    }
+
+    fn frequency(&self, key: &str) -> u8 {
+        Self::indexes(key)
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap()
+    }
+
+    fn increment(&mut self, key: &str) {
+        for index in Self::indexes(key) {
+            let counter = &mut self.counters[index];
+            *counter = (*counter + 1).min(MAX_FREQUENCY);
+        }
+
+        self.additions += 1;
+        if self.additions == SAMPLE_SIZE {
+            for counter in self.counters.iter_mut() {
+                *counter /= 2;
+            }
+            self.additions /= 2;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Region {
+    /// Where new items start, a small LRU
+    Window,
+    /// Items admitted from the window, first to be evicted
+    Probation,
+    /// Items used again while on probation
+    Protected,
+}
+
+#[derive(Debug)]
+struct Position {
+    region: Region,
+    /// Index of the key in the LRU of its region
+    node: usize,
+}
+
+/**
+ * W-TinyLFU: new items go through a small LRU window, and once the class is
+ * full, leaving it they only take the place of the next victim of the main
+ * segmented LRU if they were used more often, which keeps scans of keys read
+ * once from flushing the items used all the time.
+ */
+#[derive(Debug)]
+pub struct WTinyLfu {
+    positions: HashMap<String, Position>,
+    regions: [Lru; 3],
+    sketch: FrequencySketch,
+    /// Items moved to probation by the last insertion, oldest first
+    candidates: VecDeque<String>,
+}
+
+impl Default for WTinyLfu {
+    fn default() -> Self {
+        WTinyLfu {
+            positions: HashMap::new(),
+            regions: Default::default(),
+            sketch: FrequencySketch::new(),
+            candidates: VecDeque::new(),
+        }
+    }
+}
+
+impl WTinyLfu {
+    fn region(&self, region: Region) -> &Lru {
+        &self.regions[region as usize]
+    }
+
+    fn relink(&mut self, key: &str, region: Region) {
+        let position = self.positions.get_mut(key).unwrap();
+        self.regions[position.region as usize].remove(position.node);
+        position.node = self.regions[region as usize].push_front(key.to_owned());
+        position.region = region;
+    }
+}
+
+impl EvictionPolicy for WTinyLfu {
+    /**
+     * Adds the item to the window, moving the ones it no longer has room for
+     * to probation. They stay candidates until the next item is stored, so
+     * if that needs an eviction they have to win their place.
+     */
+    fn insert(&mut self, key: &str, _: &Item) {
+        self.sketch.increment(key);
+        let node = self.regions[Region::Window as usize].push_front(key.to_owned());
+        self.positions.insert(
+            key.to_owned(),
+            Position {
+                region: Region::Window,
+                node,
+            },
+        );
+
+        self.candidates.clear();
+        let window_limit = (self.positions.len() * WINDOW_PCT / 100).max(1);
+        while self.region(Region::Window).len() > window_limit {
+            let candidate = self.region(Region::Window).back().unwrap().to_owned();
+            self.relink(&candidate, Region::Probation);
+            self.candidates.push_back(candidate);
+        }
+    }
+
+    /**
+     * Counts the use, moving items on probation to protected, which hands
+     * its least recently used items back to probation when it gets too big.
+     */
+    fn access(&mut self, key: &str) {
+        let (region, node) = match self.positions.get(key) {
+            None => return,
+            Some(position) => (position.region, position.node),
+        };
+        self.sketch.increment(key);
+
+        match region {
+            Region::Probation => {
+                self.relink(key, Region::Protected);
+                let limit = self.positions.len() * (100 - WINDOW_PCT) / 100 * PROTECTED_PCT / 100;
+                while self.region(Region::Protected).len() > limit {
+                    let demoted = self.region(Region::Protected).back().unwrap().to_owned();
+                    self.relink(&demoted, Region::Probation);
+                }
+            }
+            region => self.regions[region as usize].touch(node),
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(position) = self.positions.remove(key) {
+            self.regions[position.region as usize].remove(position.node);
+        }
+    }
+
+    /**
+     * The oldest candidate still on probation competes with the least
+     * recently used item of the main LRU, and the one used less often is the
+     * victim. Without candidates, the main LRU gives the victim.
+     */
+    fn victim(&mut self) -> Option<String> {
+        while let Some(candidate) = self.candidates.pop_front() {
+            let on_probation = self
+                .positions
+                .get(&candidate)
+                .is_some_and(|position| position.region == Region::Probation);
+            if !on_probation {
+                continue;
+            }
+
+            let main_victim = match self.region(Region::Probation).back() {
+                Some(victim) if victim != candidate => Some(victim),
+                _ => self.region(Region::Protected).back(),
+            };
+            return match main_victim {
+                Some(victim)
+                    if self.sketch.frequency(&candidate) > self.sketch.frequency(victim) =>
+                {
+                    Some(victim.to_owned())
+                }
+                _ => Some(candidate),
+            };
+        }
+
+        [Region::Probation, Region::Protected, Region::Window]
+            .into_iter()
+            .find_map(|region| self.region(region).back())
+            .map(str::to_owned)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("number_window", self.region(Region::Window).len() as u64),
+            (
+                "number_probation",
+                self.region(Region::Probation).len() as u64,
+            ),
+            (
+                "number_protected",
+                self.region(Region::Protected).len() as u64,
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::item::tests::ItemBuilder;
+
+    use super::*;
+
+    /**
+     * Stores `key` as the store does, evicting the victim of the policy
+     * first when there are `capacity` keys already.
+     */
+    fn store(policy: &mut WTinyLfu, key: &str, capacity: usize) {
+        if policy.positions.len() == capacity {
+            let victim = policy.victim().unwrap();
+            policy.remove(&victim);
+        }
+        policy.insert(key, &ItemBuilder::new().build());
+    }
+
+    #[test]
+    fn should_estimate_frequencies_and_age_them() {
+        let mut sketch = FrequencySketch::new();
+        for _ in 0..3 {
+            sketch.increment("a");
+        }
+        assert_eq!(sketch.frequency("a"), 3);
+        assert_eq!(sketch.frequency("b"), 0);
+
+        for _ in 0..20 {
+            sketch.increment("b");
+        }
+        assert_eq!(sketch.frequency("b"), MAX_FREQUENCY);
+
+        for key in 0..SAMPLE_SIZE - 23 {
+            sketch.increment(&key.to_string());
+        }
+        assert!(sketch.frequency("b") <= MAX_FREQUENCY / 2 + 1);
+    }
+
+    #[test]
+    fn should_keep_frequently_used_items_through_a_scan() {
+        let mut policy = WTinyLfu::default();
+        let hot: Vec<String> = (0..8).map(|key| format!("hot{}", key)).collect();
+        for key in &hot {
+            store(&mut policy, key, 10);
+        }
+        for _ in 0..5 {
+            for key in &hot {
+                policy.access(key);
+            }
+        }
+
+        for key in 0..1000 {
+            store(&mut policy, &format!("scan{}", key), 10);
+        }
+
+        assert_eq!(policy.positions.len(), 10);
+        for key in &hot {
+            assert!(policy.positions.contains_key(key), "{} evicted", key);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+/**
+ * Recency order of the keys in the store, most recently used first.
+ *
+ * Nodes live in a vector and link to each other by index, so every operation
+ * is O(1) given the index returned by `push_front`, which the store keeps next
+ * to each item. Indexes of removed nodes are reused by later insertions.
+ */
+#[derive(Debug, Default)]
+pub struct Lru {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Node {
+    key: String,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl Lru {
+    /**
+     * Adds `key` as the most recently used one, returning the index of its
+     * node.
+     */
+    pub fn push_front(&mut self, key: String) -> usize {
+        let node = Node {
+            key,
+            prev: None,
+            next: None,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        self.link_front(index);
+
+        index
+    }
+
+    /**
+     * Makes the node at `index` the most recently used one.
+     */
+    pub fn touch(&mut self, index: usize) {
+        if self.head == Some(index) {
+            return;
+        }
+
+        self.unlink(index);
+        self.link_front(index);
+    }
+
+    /**
+     * Removes the node at `index`, returning its key.
+     */
+    pub fn remove(&mut self, index: usize) -> String {
+        self.unlink(index);
+        self.free.push(index);
+
+        std::mem::take(&mut self.nodes[index].key)
+    }
+
+    /**
+     * Key of the least recently used node.
+     */
+    pub fn back(&self) -> Option<&str> {
+        self.tail.map(|index| self.nodes[index].key.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    fn link_front(&mut self, index: usize) {
+        self.nodes[index].prev = None;
+        self.nodes[index].next = self.head;
+        match self.head {
+            None => self.tail = Some(index),
+            Some(head) => self.nodes[head].prev = Some(index),
+        }
+        self.head = Some(index);
+    }
+
+    /* prev -> node -> next  becomes  prev -> next */
+    fn unlink(&mut self, index: usize) {
+        let prev = self.nodes[index].prev.take();
+        let next = self.nodes[index].next.take();
+
+        match prev {
+            None => self.head = next,
+            Some(prev) => self.nodes[prev].next = next,
+        }
+        match next {
+            None => self.tail = prev,
+            Some(next) => self.nodes[next].prev = prev,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+
+    use super::*;
+
+    fn keys(lru: &Lru) -> Vec<String> {
+        let mut keys = vec![];
+        let mut node = lru.head;
+        while let Some(index) = node {
+            keys.push(lru.nodes[index].key.clone());
+            node = lru.nodes[index].next;
+        }
+
+        keys
+    }
+
+    /**
+     * xorshift64, enough to generate reproducible operations without an
+     * external crate.
+     */
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    #[test]
+    fn should_keep_keys_from_most_to_least_recently_used() {
+        let mut lru = Lru::default();
+        let a = lru.push_front("a".to_owned());
+        lru.push_front("b".to_owned());
+        let c = lru.push_front("c".to_owned());
+        assert_eq!(keys(&lru), ["c", "b", "a"]);
+
+        lru.touch(a);
+        assert_eq!(keys(&lru), ["a", "c", "b"]);
+        assert_eq!(lru.back(), Some("b"));
+
+        assert_eq!(lru.remove(c), "c");
+        assert_eq!(keys(&lru), ["a", "b"]);
+
+        // the index of the removed node is reused
+        assert_eq!(lru.push_front("d".to_owned()), c);
+        assert_eq!(keys(&lru), ["d", "a", "b"]);
+        assert_eq!(lru.len(), 3);
+    }
+
+    #[test]
+    fn should_behave_like_a_reference_model_under_random_operations() {
+        for seed in 1..=20 {
+            let mut random = Random(seed);
+            let mut lru = Lru::default();
+            let mut indexes: HashMap<String, usize> = HashMap::new();
+            // most recently used first
+            let mut model: VecDeque<String> = VecDeque::new();
+
+            for _ in 0..2000 {
+                let key = format!("key{}", random.next(50));
+                let position = model.iter().position(|other| *other == key);
+
+                match (random.next(4), position) {
+                    (0, Some(position)) => {
+                        assert_eq!(lru.remove(indexes.remove(&key).unwrap()), key);
+                        model.remove(position);
+                    }
+                    (1, _) => {
+                        if let Some(back) = model.pop_back() {
+                            assert_eq!(lru.back(), Some(back.as_str()));
+                            lru.remove(indexes.remove(&back).unwrap());
+                        }
+                    }
+                    (_, Some(position)) => {
+                        lru.touch(indexes[&key]);
+                        model.remove(position);
+                        model.push_front(key);
+                    }
+                    (_, None) => {
+                        indexes.insert(key.clone(), lru.push_front(key.clone()));
+                        model.push_front(key);
+                    }
+                }
+
+                assert_eq!(keys(&lru), Vec::from(model.clone()), "seed {}", seed);
+                assert_eq!(lru.len(), model.len());
+                assert_eq!(lru.back(), model.back().map(String::as_str));
+            }
+        }
+    }
+
+    #[test]
+    fn should_be_shareable_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Lru>();
+    }
+}
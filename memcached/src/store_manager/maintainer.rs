@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use crate::types::Store;
+
+/// Pause between passes while there are items to move
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+/// Longest pause between passes, reached while the store stays settled
+const MAX_SLEEP: Duration = Duration::from_secs(1);
+
+/**
+ * Background task running the maintenance of the eviction policy of every
+ * slab class, such as keeping the segments of the LRU in shape, so commands
+ * never move items around besides the one they work on. It runs
+ * again right away while it finds work, and backs off while it does not.
+ */
+pub async fn run(store: Store) {
+    let mut sleep = MIN_SLEEP;
+
+    loop {
+        tokio::time::sleep(sleep).await;
+        let juggles = store.maintain();
+        sleep = match juggles {
+            0 => (sleep * 2).min(MAX_SLEEP),
+            _ => MIN_SLEEP,
+        };
+    }
+}
@@ -1,116 +1,426 @@
-mod list;
+pub mod crawler;
+pub mod eviction;
+mod lru;
+pub mod maintainer;
+mod sharded;
+mod slabs;
 
 use std::collections::HashMap;
 
-use crate::{item::Item, types::MAX_ALLOWED_ITEMS};
+use crate::{config::MyConfig, item::Item};
 
-use self::list::List;
+pub use self::sharded::{ClassStats, Cursor, ShardedStore};
+pub use self::slabs::SlabClass;
+use self::slabs::Slabs;
+
+#[derive(Debug)]
+struct Entry {
+    item: Item,
+    /// Slab class whose chunk the item takes
+    class: usize,
+    /// Index of the key in `keys`
+    slot: usize,
+}
+
+/**
+ * Returned when an item can not be stored: every page is assigned and its
+ * class has nothing left to evict.
+ */
+#[derive(Debug, PartialEq)]
+pub struct OutOfMemory;
+
+/**
+ * Item found by a scan of the store.
+ */
+#[derive(Debug, Clone)]
+pub struct ScannedItem {
+    pub key: String,
+    pub item: Item,
+    /// Id of its slab class, counting from 1
+    pub class_id: usize,
+}
 
 #[derive(Debug)]
 pub struct StoreManager {
-    store: HashMap<String, Item>,
-    /**
-     * This will fake that the store will reached full capacity
-     */
-    max_allowed_items: usize,
-    list: List,
+    store: HashMap<String, Entry>,
+    /// Every key stored, in no particular order, for the items to be crawled
+    /// a few at a time
+    keys: Vec<String>,
+    slabs: Slabs,
+    /// Bytes taken by the items stored
+    bytes: usize,
+    /// Items moved around by the maintainer
+    juggles: u64,
+    /// Highest CAS unique invalidated by flush_all
+    flushed: u64,
 }
 
 impl StoreManager {
-    fn new(max_allowed_items: usize) -> StoreManager {
+    pub fn new(config: &MyConfig) -> StoreManager {
         StoreManager {
             store: HashMap::new(),
-            max_allowed_items,
-            list: List::default(),
+            keys: vec![],
+            slabs: Slabs::new(config),
+            bytes: 0,
+            juggles: 0,
+            flushed: 0,
         }
     }
 
-    pub fn insert_or_update(&mut self, key: String, value: Item) {
-        if self.store.len() < self.max_allowed_items {
-            self.store.insert(key.clone(), value);
-            if !self.list.find_and_move_first_place(&key) {
-                self.list.insert_at_the_beginning(&key);
+    /**
+     * Stores the item, counting it in a chunk of its class, see
+     * `reserve_chunk` for the items evicted when there is none. The item
+     * replaced is only removed once the new one has its chunk, so it stays
+     * when there is no room, and the new one keeps its slot, so a crawl or a
+     * scan going on still finds it. Returns the items evicted.
+     */
+    pub fn insert_or_update(
+        &mut self,
+        key: String,
+        value: Item,
+    ) -> Result<Vec<(String, Item)>, OutOfMemory> {
+        let class = self.slabs.class_for(value.size(&key)).ok_or(OutOfMemory)?;
+
+        let mut evicted = vec![];
+        // an item of the same class hands its chunk over
+        if self
+            .store
+            .get(&key)
+            .is_none_or(|entry| entry.class != class)
+        {
+            self.reserve_chunk(class, &mut evicted)?;
+            evicted.retain(|(evicted_key, _)| *evicted_key != key);
+        }
+
+        // the item replaced leaves its class, but not its slot
+        let slot = match self.store.get(&key) {
+            Some(entry) => {
+                let (old_class, old_size, slot) = (entry.class, entry.item.size(&key), entry.slot);
+                self.slabs.class_mut(old_class).policy.remove(&key);
+                if old_class != class {
+                    self.slabs.release_chunk(old_class);
+                }
+                self.bytes -= old_size;
+                slot
+            }
+            None => {
+                self.keys.push(key.clone());
+                self.keys.len() - 1
             }
-            return;
+        };
+
+        self.bytes += value.size(&key);
+        self.slabs.class_mut(class).policy.insert(&key, &value);
+        self.store.insert(
+            key,
+            Entry {
+                item: value,
+                class,
+                slot,
+            },
+        );
+
+        Ok(evicted)
+    }
+
+    /**
+     * Takes a chunk of the class for an item. When every page is assigned,
+     * a class which can spare a page hands it over, or else the eviction
+     * policy of the class picks an item to evict, or when it has none, a page
+     * is moved from the class where it is the cheapest to free, as
+     * memcached's slab mover does it.
+     */
+    fn reserve_chunk(
+        &mut self,
+        class: usize,
+        evicted: &mut Vec<(String, Item)>,
+    ) -> Result<(), OutOfMemory> {
+        if self.slabs.take_chunk(class) {
+            return Ok(());
         }
 
-        if let Some(_) = self.store.get(&key) {
-            self.store.insert(key.clone(), value);
-            self.list.find_and_move_first_place(&key);
-            return;
+        let cheapest = self.slabs.cheapest_page(class);
+        if let Some(from) =
+            cheapest.filter(|&from| self.slabs.classes()[from].evictions_for_page() == 0)
+        {
+            self.slabs.reassign(from, class)?;
+        } else if let Some(key) = self.slabs.class_mut(class).policy.victim() {
+            self.evict(&key, evicted)?;
+        } else {
+            self.move_page(cheapest.ok_or(OutOfMemory)?, class, evicted)?;
         }
 
-        let key_to_replace = self.list.last_value().unwrap();
-        self.store.remove(&key_to_replace);
-        self.store.insert(key.clone(), value);
-        self.list.insert_at_beginning_and_drop_last_node(&key);
+        if !self.slabs.take_chunk(class) {
+            return Err(OutOfMemory);
+        }
+        Ok(())
     }
 
-    pub fn get(&self, key: String) -> Option<&Item> {
-        self.store.get(&key)
+    /**
+     * Evicts the victims of the policy of class `from` until it has a page's
+     * worth of free chunks, at most the chunks of a page, and moves that page
+     * to class `to`.
+     */
+    fn move_page(
+        &mut self,
+        from: usize,
+        to: usize,
+        evicted: &mut Vec<(String, Item)>,
+    ) -> Result<(), OutOfMemory> {
+        for _ in 0..self.slabs.classes()[from].evictions_for_page() {
+            let key = self.slabs.class_mut(from).policy.victim();
+            self.evict(&key.ok_or(OutOfMemory)?, evicted)?;
+        }
+
+        self.slabs.reassign(from, to)
     }
-}
 
-impl Default for StoreManager {
-    fn default() -> Self {
-        StoreManager {
-            store: HashMap::new(),
-            max_allowed_items: MAX_ALLOWED_ITEMS,
-            list: List::default(),
+    /**
+     * Removes the item picked by the eviction policy of its class, counting
+     * it as evicted unless it had expired.
+     */
+    fn evict(&mut self, key: &str, evicted: &mut Vec<(String, Item)>) -> Result<(), OutOfMemory> {
+        let class = self.store.get(key).ok_or(OutOfMemory)?.class;
+        let item = self.remove(key).ok_or(OutOfMemory)?;
+        if !self.is_expired(&item) {
+            self.slabs.class_mut(class).evicted += 1;
+        }
+        evicted.push((key.to_owned(), item));
+
+        Ok(())
+    }
+
+    /**
+     * Looks the item up without counting it as a use.
+     */
+    pub fn get(&self, key: &str) -> Option<&Item> {
+        self.store.get(key).map(|entry| &entry.item)
+    }
+
+    /**
+     * Looks the item up, telling the eviction policy of its class it was
+     * used. Changes to the size of the item have to go through
+     * `insert_or_update` instead, for them to be accounted for.
+     */
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Item> {
+        let entry = self.store.get_mut(key)?;
+        self.slabs.class_mut(entry.class).policy.access(key);
+
+        Some(&mut entry.item)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Item> {
+        let entry = self.store.remove(key)?;
+        self.slabs.class_mut(entry.class).policy.remove(key);
+        self.slabs.release_chunk(entry.class);
+        self.bytes -= entry.item.size(key);
+
+        self.keys.swap_remove(entry.slot);
+        // the last key took the place of the removed one
+        if let Some(moved) = self.keys.get(entry.slot) {
+            self.store.get_mut(moved).unwrap().slot = entry.slot;
         }
+
+        Some(entry.item)
+    }
+
+    /**
+     * Whether the item is past its expiration time or was stored before the
+     * last flush_all of this store.
+     */
+    pub fn is_expired(&self, item: &Item) -> bool {
+        item.expired() || item.cas_unique <= self.flushed
+    }
+
+    /**
+     * Invalidates every item whose CAS unique is up to `cas_unique`.
+     */
+    pub fn flush(&mut self, cas_unique: u64) {
+        self.flushed = self.flushed.max(cas_unique);
+    }
+
+    /**
+     * Removes an expired item, counting it in its class, as unfetched too
+     * when it was never fetched.
+     */
+    pub fn reclaim(&mut self, key: &str) -> Option<Item> {
+        let class = self.store.get(key)?.class;
+        let item = self.remove(key)?;
+        let class = self.slabs.class_mut(class);
+        class.reclaimed += 1;
+        if !item.fetched {
+            class.expired_unfetched += 1;
+        }
+
+        Some(item)
+    }
+
+    /**
+     * Checks the items of the `limit` slots below `cursor`, reclaiming the
+     * expired ones, and returns them. The cursor moves down to the slot to go
+     * on from, 0 once the crawl is over: a crawl starts at `usize::MAX` and
+     * goes from the last slot to the first, so an item removed meanwhile only
+     * ever hands its slot to one already checked, and the rest are all
+     * checked however many are removed between calls.
+     */
+    pub fn reap(&mut self, cursor: &mut usize, limit: usize) -> Vec<Item> {
+        *cursor = (*cursor).min(self.keys.len());
+        let end = cursor.saturating_sub(limit);
+        let mut reclaimed = vec![];
+
+        while *cursor > end {
+            *cursor -= 1;
+            let key = &self.keys[*cursor];
+            if self.is_expired(&self.store[key].item) {
+                let key = key.clone();
+                reclaimed.extend(self.reclaim(&key));
+            }
+        }
+
+        reclaimed
+    }
+
+    /**
+     * Copies the live items of the `limit` slots below `cursor`, which moves
+     * down as `reap` does it, expired items being left out.
+     */
+    pub fn scan(&self, cursor: &mut usize, limit: usize) -> Vec<ScannedItem> {
+        *cursor = (*cursor).min(self.keys.len());
+        let end = cursor.saturating_sub(limit);
+        let scanned = self.keys[end..*cursor]
+            .iter()
+            .rev()
+            .map(|key| (key, &self.store[key]))
+            .filter(|(_, entry)| !self.is_expired(&entry.item))
+            .map(|(key, entry)| ScannedItem {
+                key: key.clone(),
+                item: entry.item.clone(),
+                class_id: entry.class + 1,
+            })
+            .collect();
+        *cursor = end;
+
+        scanned
+    }
+
+    /**
+     * One pass of the LRU maintainer over the eviction policy of every
+     * class. Returns the number of items moved around.
+     */
+    pub fn maintain(&mut self) -> usize {
+        let juggles = (0..self.slabs.classes().len())
+            .map(|class| self.slabs.class_mut(class).policy.maintain())
+            .sum();
+        self.juggles += juggles as u64;
+
+        juggles
+    }
+
+    /**
+     * Id of the slab class of the item, counting from 1 as memcached does.
+     */
+    pub fn class_id(&self, key: &str) -> Option<usize> {
+        self.store.get(key).map(|entry| entry.class + 1)
+    }
+
+    pub fn slab_classes(&self) -> &[SlabClass] {
+        self.slabs.classes()
+    }
+
+    pub fn total_malloced(&self) -> usize {
+        self.slabs.total_malloced()
+    }
+
+    pub fn juggles(&self) -> u64 {
+        self.juggles
+    }
+
+    pub fn slabs_moved(&self) -> u64 {
+        self.slabs.moved()
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::item::tests::ItemBuilder;
+    use bytes::Bytes;
+
+    use crate::{
+        config::Eviction,
+        item::{tests::ItemBuilder, ITEM_HEADER_SIZE},
+    };
 
     use super::*;
 
+    const PAGE_SIZE: usize = 128;
+
+    /**
+     * Store with classes of 72, 96 and 128 bytes chunks and room for `pages`
+     * pages. Items built by `ItemBuilder` with keys of up to 4 bytes take a
+     * 72 bytes chunk, one per page.
+     */
+    fn create_store(pages: usize) -> StoreManager {
+        let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+        config.max_item_size = PAGE_SIZE;
+        config.min_chunk_size = 16;
+        config.memory_limit = pages * PAGE_SIZE;
+
+        StoreManager::new(&config)
+    }
+
     #[test]
     fn should_insert_item_due_to_enough_space() {
-        let mut st_manager = StoreManager::new(2);
+        let mut st_manager = create_store(2);
         let key = "key".to_owned();
         let item = ItemBuilder::new().build();
 
-        st_manager.insert_or_update(key, item);
+        st_manager.insert_or_update(key, item).unwrap();
 
         assert_eq!(st_manager.store.len(), 1);
     }
 
     #[test]
     fn should_insert_second_item_due_to_enough_space() {
-        let mut st_manager = StoreManager::new(2);
+        let mut st_manager = create_store(2);
         let key = "key".to_owned();
         let item = ItemBuilder::new().build();
 
-        st_manager.insert_or_update(key, item.clone());
+        st_manager.insert_or_update(key, item.clone()).unwrap();
 
         let key2 = "key2".to_owned();
-        st_manager.insert_or_update(key2, item);
+        st_manager.insert_or_update(key2, item).unwrap();
         assert_eq!(st_manager.store.len(), 2);
     }
 
     #[test]
     fn should_replace_item_when_there_is_no_space_left() {
-        let mut st_manager = StoreManager::new(1);
+        let mut st_manager = create_store(1);
         let key = "key".to_owned();
         let item = ItemBuilder::new().build();
 
-        st_manager.insert_or_update(key, item);
+        st_manager.insert_or_update(key, item).unwrap();
 
         let key2 = "key2".to_owned();
         let item2 = ItemBuilder::new().build();
-        st_manager.insert_or_update(key2.clone(), item2.clone());
+        st_manager
+            .insert_or_update(key2.clone(), item2.clone())
+            .unwrap();
         assert_eq!(st_manager.store.len(), 1);
 
-        let stored_item = st_manager.get(key2).unwrap();
+        let stored_item = st_manager.get(&key2).unwrap();
         assert_eq!(stored_item.value, item2.value);
     }
 
     #[test]
     fn should_replace_least_used_item_when_there_is_no_space_left() {
-        let mut st_manager = StoreManager::new(3);
+        let mut st_manager = create_store(3);
         let key = "key".to_owned();
         let item = ItemBuilder::new().build();
         let key2 = "key2".to_owned();
@@ -118,61 +428,305 @@ mod tests {
         let key3 = "key3".to_owned();
         let item3 = ItemBuilder::new().build();
 
-        st_manager.insert_or_update(key.clone(), item.clone());
-        st_manager.insert_or_update(key2.clone(), item2.clone());
-        st_manager.insert_or_update(key3.clone(), item3.clone());
+        st_manager
+            .insert_or_update(key.clone(), item.clone())
+            .unwrap();
+        st_manager
+            .insert_or_update(key2.clone(), item2.clone())
+            .unwrap();
+        st_manager
+            .insert_or_update(key3.clone(), item3.clone())
+            .unwrap();
 
-        assert_eq!(st_manager.store.len(), st_manager.max_allowed_items);
+        assert_eq!(st_manager.store.len(), 3);
 
-        st_manager.insert_or_update(key.clone(), item.clone());
+        st_manager
+            .insert_or_update(key.clone(), item.clone())
+            .unwrap();
 
-        assert_eq!(st_manager.store.len(), st_manager.max_allowed_items);
-        assert!(st_manager.get(key2.clone()).is_some());
-        assert!(st_manager.get(key3.clone()).is_some());
-        assert!(st_manager.get(key.clone()).is_some());
+        assert_eq!(st_manager.store.len(), 3);
+        assert!(st_manager.get(&key2).is_some());
+        assert!(st_manager.get(&key3).is_some());
+        assert!(st_manager.get(&key).is_some());
 
         let key4 = "key4".to_owned();
         let item4 = ItemBuilder::new().build();
 
-        st_manager.insert_or_update(key4.clone(), item4.clone());
-        assert_eq!(st_manager.store.len(), st_manager.max_allowed_items);
-        assert!(st_manager.get(key2).is_none());
-        assert!(st_manager.get(key3.clone()).is_some());
-        assert!(st_manager.get(key.clone()).is_some());
-        assert!(st_manager.get(key4.clone()).is_some());
+        st_manager
+            .insert_or_update(key4.clone(), item4.clone())
+            .unwrap();
+        assert_eq!(st_manager.store.len(), 3);
+        assert!(st_manager.get(&key2).is_none());
+        assert!(st_manager.get(&key3).is_some());
+        assert!(st_manager.get(&key).is_some());
+        assert!(st_manager.get(&key4).is_some());
 
         let key5 = "key5".to_owned();
         let item5 = ItemBuilder::new().build();
 
-        st_manager.insert_or_update(key5.clone(), item5.clone());
-        assert_eq!(st_manager.store.len(), st_manager.max_allowed_items);
-        assert!(st_manager.get(key3).is_none());
-        assert!(st_manager.get(key.clone()).is_some());
-        assert!(st_manager.get(key4.clone()).is_some());
-        assert!(st_manager.get(key5.clone()).is_some());
+        st_manager
+            .insert_or_update(key5.clone(), item5.clone())
+            .unwrap();
+        assert_eq!(st_manager.store.len(), 3);
+        assert!(st_manager.get(&key3).is_none());
+        assert!(st_manager.get(&key).is_some());
+        assert!(st_manager.get(&key4).is_some());
+        assert!(st_manager.get(&key5).is_some());
 
-        st_manager.insert_or_update(key.clone(), item.clone());
+        st_manager
+            .insert_or_update(key.clone(), item.clone())
+            .unwrap();
 
         let key6 = "key6".to_owned();
 
-        st_manager.insert_or_update(key6.clone(), ItemBuilder::new().build());
-        assert_eq!(st_manager.store.len(), st_manager.max_allowed_items);
-        assert!(st_manager.get(key4).is_none());
-        assert!(st_manager.get(key5.clone()).is_some());
-        assert!(st_manager.get(key.clone()).is_some());
-        assert!(st_manager.get(key6.clone()).is_some());
-
-        st_manager.insert_or_update(key6.clone(), ItemBuilder::new().build());
-        st_manager.insert_or_update(key5.clone(), ItemBuilder::new().build());
-        st_manager.insert_or_update(key.clone(), ItemBuilder::new().build());
+        st_manager
+            .insert_or_update(key6.clone(), ItemBuilder::new().build())
+            .unwrap();
+        assert_eq!(st_manager.store.len(), 3);
+        assert!(st_manager.get(&key4).is_none());
+        assert!(st_manager.get(&key5).is_some());
+        assert!(st_manager.get(&key).is_some());
+        assert!(st_manager.get(&key6).is_some());
+
+        st_manager
+            .insert_or_update(key6.clone(), ItemBuilder::new().build())
+            .unwrap();
+        st_manager
+            .insert_or_update(key5.clone(), ItemBuilder::new().build())
+            .unwrap();
+        st_manager
+            .insert_or_update(key.clone(), ItemBuilder::new().build())
+            .unwrap();
 
         let key7 = "key7".to_owned();
 
-        st_manager.insert_or_update(key7.clone(), ItemBuilder::new().build());
-        assert_eq!(st_manager.store.len(), st_manager.max_allowed_items);
-        assert!(st_manager.get(key6).is_none());
-        assert!(st_manager.get(key5.clone()).is_some());
-        assert!(st_manager.get(key.clone()).is_some());
-        assert!(st_manager.get(key7.clone()).is_some());
+        st_manager
+            .insert_or_update(key7.clone(), ItemBuilder::new().build())
+            .unwrap();
+        assert_eq!(st_manager.store.len(), 3);
+        assert!(st_manager.get(&key6).is_none());
+        assert!(st_manager.get(&key5).is_some());
+        assert!(st_manager.get(&key).is_some());
+        assert!(st_manager.get(&key7).is_some());
+    }
+
+    #[test]
+    fn should_count_a_get_as_a_use_and_forget_removed_items() {
+        let mut st_manager = create_store(2);
+        st_manager
+            .insert_or_update("key".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+        st_manager
+            .insert_or_update("key2".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+
+        assert!(st_manager.get_mut("key").is_some());
+        let evicted = st_manager
+            .insert_or_update("key3".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+        assert_eq!(evicted[0].0, "key2");
+
+        assert!(st_manager.remove("key").is_some());
+        assert!(st_manager.remove("key").is_none());
+        assert!(st_manager
+            .insert_or_update("key4".to_owned(), ItemBuilder::new().build())
+            .unwrap()
+            .is_empty());
+        assert_eq!(st_manager.len(), 2);
+    }
+
+    #[test]
+    fn should_only_evict_items_of_the_same_class() {
+        let mut st_manager = create_store(3);
+        st_manager
+            .insert_or_update("key1".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+        st_manager
+            .insert_or_update("key2".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+
+        // 100 bytes, a chunk of the last class
+        let mut big = ItemBuilder::new().build();
+        big.update_value(Bytes::from(vec![b'a'; 40]));
+        st_manager
+            .insert_or_update("key3".to_owned(), big.clone())
+            .unwrap();
+        assert_eq!(st_manager.class_id("key3"), Some(3));
+        assert_eq!(st_manager.total_malloced(), 3 * PAGE_SIZE);
+
+        let evicted = st_manager.insert_or_update("key4".to_owned(), big).unwrap();
+        assert_eq!(evicted[0].0, "key3");
+        assert_eq!(st_manager.slab_classes()[2].evicted, 1);
+        assert!(st_manager.get("key1").is_some());
+        assert!(st_manager.get("key2").is_some());
+
+        let mut too_big = ItemBuilder::new().build();
+        too_big.update_value(Bytes::from(vec![b'a'; PAGE_SIZE]));
+        assert_eq!(
+            st_manager
+                .insert_or_update("key5".to_owned(), too_big)
+                .err(),
+            Some(OutOfMemory)
+        );
+        assert_eq!(st_manager.len(), 3);
+    }
+
+    #[test]
+    fn should_move_pages_to_a_class_without_items_to_evict() {
+        let mut st_manager = create_store(3);
+        for key in ["key1", "key2", "key3"] {
+            st_manager
+                .insert_or_update(key.to_owned(), ItemBuilder::new().build())
+                .unwrap();
+        }
+        st_manager.remove("key3");
+
+        // 96 bytes, a chunk of the second class, which takes the free page
+        let mut medium = ItemBuilder::new().build();
+        medium.update_value(Bytes::from(vec![b'a'; 30]));
+        let evicted = st_manager
+            .insert_or_update("key4".to_owned(), medium)
+            .unwrap();
+        assert!(evicted.is_empty());
+        assert_eq!(st_manager.slab_classes()[0].pages, 2);
+        assert_eq!(st_manager.slabs_moved(), 1);
+
+        // no class can spare a page, the one with the most pages evicts for one
+        let mut big = ItemBuilder::new().build();
+        big.update_value(Bytes::from(vec![b'a'; 40]));
+        let evicted = st_manager.insert_or_update("key5".to_owned(), big).unwrap();
+        assert_eq!(evicted[0].0, "key1");
+        assert_eq!(st_manager.slab_classes()[0].evicted, 1);
+        assert_eq!(st_manager.slab_classes()[0].pages, 1);
+        assert_eq!(st_manager.slab_classes()[2].pages, 1);
+        assert_eq!(st_manager.slabs_moved(), 2);
+        assert_eq!(st_manager.total_malloced(), 3 * PAGE_SIZE);
+        assert_eq!(st_manager.len(), 3);
+    }
+
+    #[test]
+    fn should_only_evict_a_page_worth_of_items_to_move_it() {
+        let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+        config.max_item_size = 2 * PAGE_SIZE;
+        config.min_chunk_size = 16;
+        config.memory_limit = 4 * PAGE_SIZE;
+        let mut st_manager = StoreManager::new(&config);
+        // three 72 bytes chunks a page
+        for key in 0..6 {
+            st_manager
+                .insert_or_update(format!("key{}", key), ItemBuilder::new().build())
+                .unwrap();
+        }
+        st_manager.remove("key5");
+
+        let mut whole_page = ItemBuilder::new().build();
+        whole_page.update_value(Bytes::from(vec![
+            b'a';
+            2 * PAGE_SIZE - ITEM_HEADER_SIZE - 4
+        ]));
+        let evicted = st_manager
+            .insert_or_update("key6".to_owned(), whole_page)
+            .unwrap();
+        let evicted: Vec<&str> = evicted.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(evicted, ["key0", "key1"]);
+        assert_eq!(st_manager.slab_classes()[0].pages, 1);
+        assert_eq!(st_manager.slab_classes()[0].used_chunks, 3);
+        assert_eq!(
+            st_manager.class_id("key6"),
+            Some(st_manager.slab_classes().len())
+        );
+        assert_eq!(st_manager.slabs_moved(), 1);
+        assert_eq!(st_manager.len(), 4);
+    }
+
+    #[test]
+    fn should_move_an_item_to_another_class_when_memory_is_full() {
+        let mut st_manager = create_store(1);
+        st_manager
+            .insert_or_update("key1".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+
+        // the only page goes to the new class once the item leaves the old one
+        let mut medium = ItemBuilder::new().build();
+        medium.update_value(Bytes::from(vec![b'a'; 30]));
+        let evicted = st_manager
+            .insert_or_update("key1".to_owned(), medium)
+            .unwrap();
+        assert!(evicted.is_empty());
+        assert_eq!(st_manager.class_id("key1"), Some(2));
+        assert_eq!(st_manager.get("key1").unwrap().value_length, 30);
+        assert_eq!(st_manager.len(), 1);
+    }
+
+    #[test]
+    fn should_move_an_item_to_the_class_of_its_new_size() {
+        let mut st_manager = create_store(2);
+        st_manager
+            .insert_or_update("key1".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+        assert_eq!(st_manager.class_id("key1"), Some(1));
+
+        let mut big = ItemBuilder::new().build();
+        big.update_value(Bytes::from(vec![b'a'; 40]));
+        st_manager.insert_or_update("key1".to_owned(), big).unwrap();
+        assert_eq!(st_manager.class_id("key1"), Some(3));
+        assert_eq!(st_manager.slab_classes()[0].used_chunks, 0);
+        assert_eq!(st_manager.slab_classes()[2].used_chunks, 1);
+        assert_eq!(st_manager.bytes(), ITEM_HEADER_SIZE + "key1".len() + 40);
+
+        st_manager.remove("key1");
+        assert_eq!(st_manager.bytes(), 0);
+        assert_eq!(st_manager.slab_classes()[2].free_chunks(), 1);
+    }
+
+    #[test]
+    fn should_evict_the_victims_of_the_configured_policy() {
+        let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+        config.max_item_size = PAGE_SIZE;
+        config.min_chunk_size = 16;
+        config.memory_limit = 3 * PAGE_SIZE;
+        config.eviction_policy = Eviction::Lfu;
+        let mut st_manager = StoreManager::new(&config);
+        for key in ["key1", "key2", "key3"] {
+            st_manager
+                .insert_or_update(key.to_owned(), ItemBuilder::new().build())
+                .unwrap();
+        }
+        st_manager.get_mut("key1");
+        st_manager.get_mut("key2");
+
+        let evicted = st_manager
+            .insert_or_update("key4".to_owned(), ItemBuilder::new().build())
+            .unwrap();
+        assert_eq!(evicted[0].0, "key3");
+        assert_eq!(st_manager.slab_classes()[0].evicted, 1);
+    }
+
+    #[test]
+    fn should_reap_expired_items_a_few_at_a_time() {
+        let mut st_manager = create_store(10);
+        for key in 0..6 {
+            let item = match key {
+                1 | 3 | 4 => Item::new(0, -1, 4, Bytes::from("hola")),
+                _ => ItemBuilder::new().build(),
+            };
+            st_manager
+                .insert_or_update(format!("key{}", key), item)
+                .unwrap();
+        }
+
+        let mut cursor = usize::MAX;
+        assert_eq!(st_manager.reap(&mut cursor, 2).len(), 1);
+        assert_eq!(cursor, 4);
+        // key5 takes the slot of key0, one not checked yet
+        st_manager.remove("key0");
+
+        assert_eq!(st_manager.reap(&mut cursor, 10).len(), 2);
+        assert_eq!(cursor, 0);
+        assert!(st_manager.get("key5").is_some());
+        assert!(st_manager.get("key2").is_some());
+        assert_eq!(st_manager.len(), 2);
+        assert_eq!(st_manager.slab_classes()[0].reclaimed, 3);
+        assert_eq!(st_manager.slab_classes()[0].expired_unfetched, 3);
     }
 }
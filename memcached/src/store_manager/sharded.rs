@@ -0,0 +1,427 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+};
+
+use super::{ScannedItem, StoreManager};
+use crate::{config::MyConfig, item};
+
+/**
+ * Store split in shards, each of them a `StoreManager` with its own lock and
+ * an even share of the memory, picked by the hash of the key. Clients working
+ * on keys of different shards never wait for each other, and a shard running
+ * out of memory only evicts its own items.
+ */
+#[derive(Debug)]
+pub struct ShardedStore {
+    shards: Vec<Mutex<StoreManager>>,
+    hasher: RandomState,
+    memory_limit: usize,
+    /// Incremented by every flush_all, so a delayed flush only happens if no
+    /// other flush_all was received in the meantime
+    flush_generation: AtomicU64,
+}
+
+/**
+ * Figures of a slab class added up over every shard.
+ */
+#[derive(Debug, Default)]
+pub struct ClassStats {
+    pub chunk_size: usize,
+    pub chunks_per_page: usize,
+    pub pages: usize,
+    pub total_chunks: usize,
+    pub used_chunks: usize,
+    pub evicted: u64,
+    pub reclaimed: u64,
+    pub expired_unfetched: u64,
+    /// Counters of the eviction policy of the class
+    pub policy: Vec<(&'static str, u64)>,
+}
+
+/**
+ * Where a scan of the store goes on from, the default one starting it. Every
+ * key present from the start of the scan to its end is returned at least
+ * once, even when its item is replaced meanwhile, as a key keeps its place
+ * in the shard until removed. Keys stored or removed while the scan goes on
+ * may be returned or not, maybe twice.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    shard: usize,
+    /// Slot of the shard to go on from, as `StoreManager::scan` takes it
+    slot: usize,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Cursor {
+            shard: 0,
+            slot: usize::MAX,
+        }
+    }
+}
+
+impl ShardedStore {
+    pub fn new(config: &MyConfig) -> ShardedStore {
+        let count = config.shards.unwrap_or_else(|| default_shards(config));
+        let mut shard_config = config.clone();
+        shard_config.memory_limit = config.memory_limit / count;
+
+        ShardedStore {
+            shards: (0..count)
+                .map(|_| Mutex::new(StoreManager::new(&shard_config)))
+                .collect(),
+            hasher: RandomState::new(),
+            memory_limit: config.memory_limit,
+            flush_generation: AtomicU64::new(0),
+        }
+    }
+
+    /**
+     * Locks the shard `key` belongs to.
+     */
+    pub fn lock(&self, key: &str) -> MutexGuard<'_, StoreManager> {
+        let index = self.hasher.hash_one(key) % self.shards.len() as u64;
+
+        lock(&self.shards[index as usize])
+    }
+
+    /**
+     * Locks the shard at `index`, below `shard_count`.
+     */
+    pub fn lock_shard(&self, index: usize) -> MutexGuard<'_, StoreManager> {
+        lock(&self.shards[index])
+    }
+
+    /**
+     * Locks every shard in turn, each one released before the next is
+     * locked.
+     */
+    pub fn shards(&self) -> impl Iterator<Item = MutexGuard<'_, StoreManager>> {
+        self.shards.iter().map(lock)
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards().all(|shard| shard.len() == 0)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.shards().map(|shard| shard.bytes()).sum()
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub fn total_malloced(&self) -> usize {
+        self.shards().map(|shard| shard.total_malloced()).sum()
+    }
+
+    pub fn juggles(&self) -> u64 {
+        self.shards().map(|shard| shard.juggles()).sum()
+    }
+
+    pub fn slabs_moved(&self) -> u64 {
+        self.shards().map(|shard| shard.slabs_moved()).sum()
+    }
+
+    /**
+     * Invalidates every item stored up to now in this store, whatever other
+     * stores there may be.
+     */
+    pub fn flush_all(&self) {
+        let cas_unique = item::last_cas_unique();
+        for mut shard in self.shards() {
+            shard.flush(cas_unique);
+        }
+    }
+
+    /**
+     * Starts a new flush_all generation, replacing any pending flush, and
+     * returns it.
+     */
+    pub fn new_flush_generation(&self) -> u64 {
+        self.flush_generation.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn flush_generation(&self) -> u64 {
+        self.flush_generation.load(Ordering::Relaxed)
+    }
+
+    /**
+     * Returns up to `count` live items from `cursor` on, and the cursor of
+     * the next ones, `None` once every item was returned. Shards are locked
+     * for at most `count` items at a time, so scanning the whole store a page
+     * at a time never keeps other clients waiting for long.
+     */
+    pub fn scan(&self, cursor: Cursor, count: usize) -> (Vec<ScannedItem>, Option<Cursor>) {
+        let count = count.max(1);
+        let mut cursor = cursor;
+        let mut items = vec![];
+
+        while items.len() < count {
+            let shard = match self.shards.get(cursor.shard) {
+                None => return (items, None),
+                Some(shard) => shard,
+            };
+            items.extend(lock(shard).scan(&mut cursor.slot, count - items.len()));
+            if cursor.slot == 0 {
+                cursor = Cursor {
+                    shard: cursor.shard + 1,
+                    ..Cursor::default()
+                };
+            }
+        }
+
+        match cursor.shard < self.shards.len() {
+            true => (items, Some(cursor)),
+            false => (items, None),
+        }
+    }
+
+    /**
+     * Runs the maintenance of every shard, returning the items moved.
+     */
+    pub fn maintain(&self) -> usize {
+        self.shards().map(|mut shard| shard.maintain()).sum()
+    }
+
+    /**
+     * Every slab class, in order, with the figures of all the shards, which
+     * share the same classes.
+     */
+    pub fn class_stats(&self) -> Vec<ClassStats> {
+        let mut classes: Vec<ClassStats> = vec![];
+
+        for shard in self.shards() {
+            classes.resize_with(shard.slab_classes().len(), Default::default);
+            for (stats, class) in classes.iter_mut().zip(shard.slab_classes()) {
+                stats.chunk_size = class.chunk_size;
+                stats.chunks_per_page = class.chunks_per_page;
+                stats.pages += class.pages;
+                stats.total_chunks += class.total_chunks();
+                stats.used_chunks += class.used_chunks;
+                stats.evicted += class.evicted;
+                stats.reclaimed += class.reclaimed;
+                stats.expired_unfetched += class.expired_unfetched;
+
+                let policy = class.policy().stats();
+                stats.policy.resize(policy.len(), ("", 0));
+                for (total, (name, value)) in stats.policy.iter_mut().zip(policy) {
+                    *total = (name, total.1 + value);
+                }
+            }
+        }
+
+        classes
+    }
+}
+
+/**
+ * One shard per core, fewer only when the memory is so small that a shard
+ * would not have room for two items of the maximum size. Pages move between
+ * the slab classes of a shard, so a few of them are enough for every size.
+ */
+fn default_shards(config: &MyConfig) -> usize {
+    let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+
+    (config.memory_limit / (2 * config.max_item_size)).clamp(1, cores)
+}
+
+/**
+ * A client panicking while holding the lock of a shard poisons it, but the
+ * shard is still handed out: failing every later command on it, or on the
+ * whole server, would be worse than the item being updated being left half
+ * done.
+ */
+fn lock(shard: &Mutex<StoreManager>) -> MutexGuard<'_, StoreManager> {
+    shard.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use bytes::Bytes;
+
+    use crate::item::Item;
+
+    use super::*;
+
+    fn create_store(shards: usize, memory_limit: usize) -> ShardedStore {
+        let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+        config.memory_limit = memory_limit;
+        config.shards = Some(shards);
+
+        ShardedStore::new(&config)
+    }
+
+    fn item() -> Item {
+        Item::new(0, 0, 4, Bytes::from("hola"))
+    }
+
+    #[test]
+    fn should_spread_keys_over_the_shards_and_add_up_their_figures() {
+        let store = create_store(4, 64 * 1024 * 1024);
+        for key in 0..100 {
+            let key = format!("key{:02}", key);
+            store.lock(&key).insert_or_update(key, item()).unwrap();
+        }
+
+        assert_eq!(store.len(), 100);
+        assert!(store.shards().all(|shard| shard.len() > 0));
+        assert!(store.lock("key42").get("key42").is_some());
+        assert_eq!(store.bytes(), 100 * item().size("key10"));
+        assert_eq!(store.class_stats()[0].used_chunks, 100);
+        // a page of 1MB for the first class of every shard
+        assert_eq!(store.total_malloced(), 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn should_default_to_a_shard_per_core() {
+        let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+        config.memory_limit = 64 * 1024 * 1024;
+        assert_eq!(default_shards(&config), cores.min(32));
+
+        // too little memory for a shard per core
+        config.memory_limit = 2 * config.max_item_size;
+        assert_eq!(default_shards(&config), 1);
+    }
+
+    #[test]
+    fn should_store_items_of_every_size_in_a_shard_with_few_pages() {
+        let store = create_store(8, 64 * 1024 * 1024);
+        let classes = store.class_stats();
+        let pages = 64 / 8;
+        assert!(classes.len() > pages);
+
+        // a size per class, more classes than pages in the shard of the key
+        for class in &classes {
+            let size = class.chunk_size - item().size("key") + 4;
+            let value = Bytes::from(vec![b'a'; size]);
+            let item = Item::new(0, 0, size, value);
+            store
+                .lock("key")
+                .insert_or_update("key".to_owned(), item)
+                .unwrap();
+            assert_eq!(store.lock("key").get("key").unwrap().value_length, size);
+        }
+    }
+
+    #[test]
+    fn should_only_flush_the_items_of_its_own_store() {
+        let flushed = create_store(4, 64 * 1024 * 1024);
+        let other = create_store(4, 64 * 1024 * 1024);
+        for store in [&flushed, &other] {
+            store
+                .lock("key")
+                .insert_or_update("key".to_owned(), item())
+                .unwrap();
+        }
+
+        flushed.flush_all();
+        let expired = |store: &ShardedStore, key: &str| {
+            let shard = store.lock(key);
+            shard.is_expired(shard.get(key).unwrap())
+        };
+        assert!(expired(&flushed, "key"));
+        assert!(!expired(&other, "key"));
+
+        // stored after the flush
+        flushed
+            .lock("key2")
+            .insert_or_update("key2".to_owned(), item())
+            .unwrap();
+        assert!(!expired(&flushed, "key2"));
+    }
+
+    #[test]
+    fn should_keep_serving_a_shard_after_a_panic_while_locked() {
+        let store = Arc::new(create_store(1, 64 * 1024 * 1024));
+
+        let poisoning = store.clone();
+        let result = thread::spawn(move || {
+            let _shard = poisoning.lock("key");
+            panic!("client crashed");
+        })
+        .join();
+        assert!(result.is_err());
+
+        let mut shard = store.lock("key");
+        shard.insert_or_update("key".to_owned(), item()).unwrap();
+        assert!(shard.get("key").is_some());
+    }
+
+    #[test]
+    fn should_scan_every_item_a_page_at_a_time() {
+        let store = create_store(4, 64 * 1024 * 1024);
+        for key in 0..50 {
+            let key = format!("key{}", key);
+            store.lock(&key).insert_or_update(key, item()).unwrap();
+        }
+        let expired = Item::new(0, -1, 4, Bytes::from("hola"));
+        store
+            .lock("expired")
+            .insert_or_update("expired".to_owned(), expired)
+            .unwrap();
+
+        let mut keys = vec![];
+        let mut cursor = Some(Cursor::default());
+        while let Some(next) = cursor {
+            let (items, next) = store.scan(next, 7);
+            assert!(items.len() <= 7);
+            // removed while scanning, whether already returned or not
+            store.lock("key0").remove("key0");
+            keys.extend(items.into_iter().map(|scanned| scanned.key));
+            cursor = next;
+        }
+
+        let mut keys: HashSet<String> = keys.into_iter().collect();
+        keys.remove("key0");
+        assert_eq!(keys, (1..50).map(|key| format!("key{}", key)).collect());
+    }
+
+    #[test]
+    fn should_scan_every_key_even_when_overwritten_meanwhile() {
+        let store = create_store(4, 64 * 1024 * 1024);
+        let keys: HashSet<String> = (0..50).map(|key| format!("key{}", key)).collect();
+        for key in &keys {
+            store
+                .lock(key)
+                .insert_or_update(key.clone(), item())
+                .unwrap();
+        }
+
+        let mut scanned = HashSet::new();
+        let mut cursor = Some(Cursor::default());
+        while let Some(next) = cursor {
+            let (items, next) = store.scan(next, 7);
+            scanned.extend(items.into_iter().map(|scanned| scanned.key));
+            // every key set again, with a value of another size
+            for key in &keys {
+                let value = Bytes::from(vec![b'a'; 100]);
+                let item = Item::new(0, 0, value.len(), value);
+                store.lock(key).insert_or_update(key.clone(), item).unwrap();
+            }
+            cursor = next;
+        }
+
+        assert_eq!(scanned, keys);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{
+    config::{Eviction, MyConfig},
+    item::ITEM_HEADER_SIZE,
+};
+
+use super::{
+    eviction::{self, EvictionPolicy},
+    OutOfMemory,
+};
+
+/// Chunk sizes are rounded up to a multiple of this
+const CHUNK_ALIGN_BYTES: usize = 8;
+/// Classes created at most, the last one always holding whole pages
+const MAX_SLAB_CLASSES: usize = 63;
+
+/**
+ * Accounting of the memory of the store as memcached lays it out, in pages of
+ * the same size handed to a slab class when it runs out of chunks. Items are
+ * still allocated on their own, this only counts the chunk each of them would
+ * take in the smallest class it fits in, for the memory limit to be enforced
+ * and reported per size. Once every page is assigned, pages move from one
+ * class to another.
+ */
+#[derive(Debug)]
+pub struct Slabs {
+    classes: Vec<SlabClass>,
+    page_size: usize,
+    max_pages: usize,
+    pages: usize,
+    /// Pages moved from one class to another
+    moved: u64,
+}
+
+#[derive(Debug)]
+pub struct SlabClass {
+    pub chunk_size: usize,
+    pub chunks_per_page: usize,
+    pub pages: usize,
+    pub used_chunks: usize,
+    /// Items evicted to make room for others of this class
+    pub evicted: u64,
+    /// Expired items removed
+    pub reclaimed: u64,
+    /// Expired items removed without ever being fetched
+    pub expired_unfetched: u64,
+    /// Picks the items of this class to evict
+    pub(super) policy: Box<dyn EvictionPolicy>,
+}
+
+impl SlabClass {
+    fn new(chunk_size: usize, page_size: usize, eviction: Eviction) -> SlabClass {
+        SlabClass {
+            chunk_size,
+            chunks_per_page: page_size / chunk_size,
+            pages: 0,
+            used_chunks: 0,
+            evicted: 0,
+            reclaimed: 0,
+            expired_unfetched: 0,
+            policy: eviction::create(eviction),
+        }
+    }
+
+    pub fn total_chunks(&self) -> usize {
+        self.pages * self.chunks_per_page
+    }
+
+    pub fn free_chunks(&self) -> usize {
+        self.total_chunks() - self.used_chunks
+    }
+
+    /**
+     * Items to evict for the class to have a page's worth of free chunks.
+     */
+    pub fn evictions_for_page(&self) -> usize {
+        self.chunks_per_page.saturating_sub(self.free_chunks())
+    }
+
+    pub fn policy(&self) -> &dyn EvictionPolicy {
+        self.policy.as_ref()
+    }
+}
+
+impl Slabs {
+    /**
+     * Creates the classes: the first one fits the item header plus the
+     * minimum chunk size (-n), every next one is the growth factor (-f)
+     * bigger, and the last one takes a whole page, whose size is the maximum
+     * item size (-I).
+     */
+    pub fn new(config: &MyConfig) -> Slabs {
+        let page_size = config.max_item_size;
+        let mut classes = vec![];
+
+        let mut chunk_size = align(ITEM_HEADER_SIZE + config.min_chunk_size);
+        while classes.len() < MAX_SLAB_CLASSES - 1
+            && (chunk_size as f64) <= page_size as f64 / config.growth_factor
+        {
+            classes.push(SlabClass::new(
+                chunk_size,
+                page_size,
+                config.eviction_policy,
+            ));
+            let next = align((chunk_size as f64 * config.growth_factor) as usize);
+            chunk_size = next.max(chunk_size + CHUNK_ALIGN_BYTES);
+        }
+        classes.push(SlabClass::new(page_size, page_size, config.eviction_policy));
+
+        Slabs {
+            classes,
+            page_size,
+            max_pages: config.memory_limit / page_size,
+            pages: 0,
+            moved: 0,
+        }
+    }
+
+    /**
+     * Index of the smallest class whose chunks fit `size` bytes, if any.
+     */
+    pub fn class_for(&self, size: usize) -> Option<usize> {
+        self.classes
+            .iter()
+            .position(|class| class.chunk_size >= size)
+    }
+
+    /**
+     * Counts a chunk of the class as used, assigning it a new page if all of
+     * its chunks are. Fails once every page has been assigned.
+     */
+    pub fn take_chunk(&mut self, class: usize) -> bool {
+        let class = &mut self.classes[class];
+        if class.free_chunks() == 0 {
+            if self.pages == self.max_pages {
+                return false;
+            }
+            self.pages += 1;
+            class.pages += 1;
+        }
+
+        class.used_chunks += 1;
+        true
+    }
+
+    pub fn release_chunk(&mut self, class: usize) {
+        self.classes[class].used_chunks -= 1;
+    }
+
+    /**
+     * Class other than `class` whose page is the cheapest to take: the one
+     * needing the fewest evictions to have a page's worth of free chunks, or
+     * the one with the most pages among them.
+     */
+    pub fn cheapest_page(&self, class: usize) -> Option<usize> {
+        self.classes
+            .iter()
+            .enumerate()
+            .filter(|(other, slab)| *other != class && slab.pages > 0)
+            .min_by_key(|(_, slab)| (slab.evictions_for_page(), usize::MAX - slab.pages))
+            .map(|(other, _)| other)
+    }
+
+    /**
+     * Moves a page from class `from` to class `to`. Fails unless `from` has a
+     * page's worth of free chunks.
+     */
+    pub fn reassign(&mut self, from: usize, to: usize) -> Result<(), OutOfMemory> {
+        let from = &mut self.classes[from];
+        if from.pages == 0 || from.evictions_for_page() > 0 {
+            return Err(OutOfMemory);
+        }
+        from.pages -= 1;
+        self.classes[to].pages += 1;
+        self.moved += 1;
+
+        Ok(())
+    }
+
+    pub fn classes(&self) -> &[SlabClass] {
+        &self.classes
+    }
+
+    pub fn class_mut(&mut self, class: usize) -> &mut SlabClass {
+        &mut self.classes[class]
+    }
+
+    /**
+     * Bytes taken by the pages assigned so far.
+     */
+    pub fn total_malloced(&self) -> usize {
+        self.pages * self.page_size
+    }
+
+    pub fn moved(&self) -> u64 {
+        self.moved
+    }
+}
+
+fn align(size: usize) -> usize {
+    size.div_ceil(CHUNK_ALIGN_BYTES) * CHUNK_ALIGN_BYTES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_slabs(memory_limit: usize, page_size: usize) -> Slabs {
+        let mut config = MyConfig::parse(vec!["memcached".to_owned()].into_iter(), None).unwrap();
+        config.memory_limit = memory_limit;
+        config.max_item_size = page_size;
+
+        Slabs::new(&config)
+    }
+
+    #[test]
+    fn should_grow_chunk_sizes_by_the_factor_up_to_a_page() {
+        let slabs = create_slabs(64 * 1024 * 1024, 1024 * 1024);
+        let sizes: Vec<usize> = slabs.classes().iter().map(|c| c.chunk_size).collect();
+
+        assert_eq!(&sizes[..4], [104, 136, 176, 224]);
+        assert_eq!(sizes.last(), Some(&(1024 * 1024)));
+        assert!(sizes.iter().all(|size| size % CHUNK_ALIGN_BYTES == 0));
+        assert!(sizes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(slabs.classes()[0].chunks_per_page, 1024 * 1024 / 104);
+    }
+
+    #[test]
+    fn should_pick_the_smallest_class_an_item_fits_in() {
+        let slabs = create_slabs(64 * 1024 * 1024, 1024 * 1024);
+
+        assert_eq!(slabs.class_for(1), Some(0));
+        assert_eq!(slabs.class_for(104), Some(0));
+        assert_eq!(slabs.class_for(105), Some(1));
+        assert_eq!(
+            slabs.class_for(1024 * 1024),
+            Some(slabs.classes().len() - 1)
+        );
+        assert_eq!(slabs.class_for(1024 * 1024 + 1), None);
+    }
+
+    #[test]
+    fn should_assign_pages_to_classes_until_memory_runs_out() {
+        let mut slabs = create_slabs(2 * 1024, 1024);
+        let chunks_per_page = slabs.classes()[0].chunks_per_page;
+
+        for _ in 0..chunks_per_page {
+            assert!(slabs.take_chunk(0));
+        }
+        assert_eq!(slabs.classes()[0].pages, 1);
+
+        assert!(slabs.take_chunk(1));
+        assert_eq!(slabs.total_malloced(), 2 * 1024);
+        // no page left for a third class, nor for more chunks of the first
+        assert!(!slabs.take_chunk(2));
+        assert!(!slabs.take_chunk(0));
+
+        slabs.release_chunk(0);
+        assert_eq!(slabs.classes()[0].free_chunks(), 1);
+        assert!(slabs.take_chunk(0));
+    }
+
+    #[test]
+    fn should_move_pages_between_classes() {
+        let mut slabs = create_slabs(2 * 1024, 1024);
+        let chunks_per_page = slabs.classes()[0].chunks_per_page;
+        for _ in 0..chunks_per_page + 1 {
+            assert!(slabs.take_chunk(0));
+        }
+        assert_eq!(slabs.cheapest_page(1), Some(0));
+        assert_eq!(slabs.cheapest_page(0), None);
+        assert_eq!(slabs.reassign(0, 1), Err(OutOfMemory));
+
+        for _ in 0..chunks_per_page {
+            slabs.release_chunk(0);
+        }
+        assert_eq!(slabs.classes()[0].evictions_for_page(), 0);
+        assert_eq!(slabs.reassign(0, 1), Ok(()));
+        assert!(slabs.take_chunk(1));
+        assert_eq!(slabs.classes()[0].pages, 1);
+        assert_eq!(slabs.classes()[1].pages, 1);
+        assert_eq!(slabs.total_malloced(), 2 * 1024);
+        assert_eq!(slabs.moved(), 1);
+        assert_eq!(slabs.reassign(2, 0), Err(OutOfMemory));
+    }
+
+    #[test]
+    fn should_take_the_page_needing_the_fewest_evictions() {
+        let mut slabs = create_slabs(4 * 1024, 1024);
+        let chunks_per_page = slabs.classes()[0].chunks_per_page;
+        for _ in 0..2 * chunks_per_page {
+            assert!(slabs.take_chunk(0));
+        }
+        assert!(slabs.take_chunk(1));
+        assert!(slabs.take_chunk(2));
+        // both full pages of the first class cost more than the other ones
+        assert_eq!(slabs.cheapest_page(3), Some(1));
+
+        for _ in 0..chunks_per_page - 1 {
+            slabs.release_chunk(0);
+        }
+        // as cheap as the second class, but with more pages
+        assert_eq!(slabs.classes()[0].evictions_for_page(), 1);
+        assert_eq!(slabs.cheapest_page(3), Some(0));
+    }
+}
@@ -1,13 +1,27 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
-use crate::item::Item;
+use crate::store_manager::ShardedStore;
 
-pub type Store = Arc<Mutex<HashMap<String, Item>>>;
+pub type Store = Arc<ShardedStore>;
 
-pub const WRITE_COMMANDS: [&str; 5] = ["set", "replace", "add", "append", "prepend"];
-pub const READ_COMMANDS: [&str; 1] = ["get"];
+pub const WRITE_COMMANDS: [&str; 6] = ["set", "replace", "add", "append", "prepend", "cas"];
+pub const READ_COMMANDS: [&str; 2] = ["get", "gets"];
+pub const DELETE_COMMANDS: [&str; 1] = ["delete"];
+pub const ARITHMETIC_COMMANDS: [&str; 2] = ["incr", "decr"];
+pub const TOUCH_COMMANDS: [&str; 1] = ["touch"];
+pub const GAT_COMMANDS: [&str; 2] = ["gat", "gats"];
+pub const FLUSH_COMMANDS: [&str; 1] = ["flush_all"];
+pub const STATS_COMMANDS: [&str; 1] = ["stats"];
+pub const VERBOSITY_COMMANDS: [&str; 1] = ["verbosity"];
+pub const CRAWLER_COMMANDS: [&str; 1] = ["lru_crawler"];
+pub const META_COMMANDS: [&str; 5] = ["mg", "ms", "md", "ma", "me"];
+pub const META_SET_COMMAND: &str = "ms";
+/// Commands taking no argument at all
+pub const ADMIN_COMMANDS: [&str; 3] = ["version", "quit", "mn"];
 
-pub const MAX_ALLOWED_ITEMS: usize = 5;
\ No newline at end of file
+/// Longest key accepted, in bytes
+pub const MAX_KEY_LENGTH: usize = 250;
+
+/// Bytes the items can take by default before the least recently used ones
+/// are evicted
+pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
@@ -0,0 +1,65 @@
+use std::{
+    net::{TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+const STARTUP_ATTEMPTS: usize = 100;
+
+/**
+ * Server process listening on a free local port, killed when dropped.
+ */
+pub struct TestServer {
+    process: Child,
+    port: u16,
+}
+
+impl TestServer {
+    pub fn start() -> TestServer {
+        TestServer::start_with(&[])
+    }
+
+    /**
+     * Starts a server with extra command line options.
+     */
+    pub fn start_with(options: &[&str]) -> TestServer {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let server = TestServer {
+            process: Command::new(env!("CARGO_BIN_EXE_memcached"))
+                .args(["-p", &port.to_string()])
+                .args(options)
+                .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/.."))
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap(),
+            port,
+        };
+
+        for _ in 0..STARTUP_ATTEMPTS {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return server;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        panic!("server did not start on port {}", port);
+    }
+
+    pub fn address(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
@@ -1,4 +1,15 @@
-use memcached_client::{self, Client};
+mod common;
+
+use common::TestServer;
+use memcached_client::{self, CasResponse, Client};
+use std::{collections::HashMap, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 async fn clean_data(client: &mut Client) {
     client
@@ -7,9 +18,57 @@ async fn clean_data(client: &mut Client) {
         .unwrap();
 }
 
+async fn read_exactly(stream: &mut TcpStream, expected: &str) {
+    let mut buf = vec![0; expected.len()];
+    timeout(RESPONSE_TIMEOUT, stream.read_exact(&mut buf))
+        .await
+        .expect("timed out waiting for the response")
+        .unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}
+
+/**
+ * Sends a command and reads its response, up to and including END.
+ */
+async fn read_until_end(stream: &mut TcpStream, command: &str) -> String {
+    stream
+        .write_all(format!("{}\r\n", command).as_bytes())
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    while !response.ends_with("END\r\n") {
+        let mut buf = [0; 1024];
+        let read = timeout(RESPONSE_TIMEOUT, stream.read(&mut buf))
+            .await
+            .expect("timed out waiting for the response")
+            .unwrap();
+        assert_ne!(read, 0, "connection closed");
+        response.push_str(std::str::from_utf8(&buf[..read]).unwrap());
+    }
+
+    response
+}
+
+/**
+ * Sends a stats command and collects the STAT lines of its response.
+ */
+async fn read_stats(stream: &mut TcpStream, command: &str) -> HashMap<String, String> {
+    read_until_end(stream, command)
+        .await
+        .lines()
+        .filter_map(|line| line.strip_prefix("STAT "))
+        .map(|stat| {
+            let (name, value) = stat.split_once(' ').unwrap();
+            (name.to_owned(), value.to_owned())
+        })
+        .collect()
+}
+
 #[tokio::test]
 async fn it_should_set_and_retrieve_the_value() {
-    let mut client = memcached_client::Client::connect(vec!["127.0.0.1:1024"])
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
         .await
         .unwrap();
 
@@ -18,14 +77,15 @@ async fn it_should_set_and_retrieve_the_value() {
         .await;
 
     let data = client.get("test".to_string()).await.unwrap();
-    assert_eq!(data, "hola");
+    assert_eq!(data, Some(b"hola".to_vec()));
 
     clean_data(&mut client).await;
 }
 
 #[tokio::test]
 async fn it_should_add_and_retrieve_the_value() {
-    let mut client = memcached_client::Client::connect(vec!["127.0.0.1:1024"])
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
         .await
         .unwrap();
 
@@ -36,7 +96,1254 @@ async fn it_should_add_and_retrieve_the_value() {
     assert!(stored.unwrap());
 
     let data = client.get("test2".to_string()).await.unwrap();
-    assert_eq!(data, "hola");
+    assert_eq!(data, Some(b"hola".to_vec()));
 
     clean_data(&mut client).await;
 }
+
+#[tokio::test]
+async fn it_should_return_binary_values_byte_for_byte() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+    let value: Vec<u8> = (0..=255).chain([b'\r', b'\n']).collect();
+
+    client.set("binary".to_string(), &value, 100).await.unwrap();
+    client
+        .set("appended".to_string(), b"\xff\r\n ", 100)
+        .await
+        .unwrap();
+
+    let data = client.get("binary".to_string()).await.unwrap();
+    assert_eq!(data, Some(value));
+
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    stream
+        .write_all(b"append appended 0 100 3\r\n\x00 \n\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\n").await;
+
+    let data = client.get("appended".to_string()).await.unwrap();
+    assert_eq!(data, Some(b"\xff\r\n \x00 \n".to_vec()));
+}
+
+#[tokio::test]
+async fn it_should_process_pipelined_commands_in_order() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(
+            b"set first 0 100 3\r\none\r\nset second 0 100 3\r\ntwo\r\nget first\r\nget second\r\n",
+        )
+        .await
+        .unwrap();
+
+    read_exactly(
+        &mut stream,
+        "STORED\r\nSTORED\r\nVALUE first 0 3\r\none\r\nEND\r\nVALUE second 0 3\r\ntwo\r\nEND\r\n",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn it_should_wait_for_commands_split_across_segments() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let value = "a".repeat(10_000);
+
+    stream.write_all(b"set big 0 10").await.unwrap();
+    stream.flush().await.unwrap();
+    stream.write_all(b"0 10000\r\n").await.unwrap();
+    for chunk in value.as_bytes().chunks(3000) {
+        stream.write_all(chunk).await.unwrap();
+        stream.flush().await.unwrap();
+    }
+    stream.write_all(b"\r\nget big\r\n").await.unwrap();
+
+    read_exactly(
+        &mut stream,
+        &format!("STORED\r\nVALUE big 0 10000\r\n{}\r\nEND\r\n", value),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn it_should_answer_malformed_input_with_errors_and_keep_the_connection() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream.write_all(b"bogus command\r\n").await.unwrap();
+    read_exactly(&mut stream, "ERROR\r\n").await;
+
+    stream
+        .write_all(b"set test abc 0 1\r\nx\r\nget test\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "CLIENT_ERROR bad command line format\r\nEND\r\n",
+    )
+    .await;
+
+    stream
+        .write_all(b"set test 0 100 2\r\nhola\r\nget test\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "CLIENT_ERROR bad data chunk\r\nEND\r\n").await;
+
+    stream
+        .write_all(b"set test 0 100 4\r\nhola\r\nget test\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nVALUE test 0 4\r\nhola\r\nEND\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_delete_the_value() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+
+    client
+        .set("test".to_string(), "hola".to_string(), 100)
+        .await
+        .unwrap();
+
+    assert!(client.delete("test".to_string()).await.unwrap());
+    assert_eq!(client.get("test".to_string()).await.unwrap(), None);
+    assert!(!client.delete("test".to_string()).await.unwrap());
+}
+
+#[tokio::test]
+async fn it_should_delete_without_reply() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set test 0 100 4\r\nhola\r\ndelete test noreply\r\ndelete test\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nNOT_FOUND\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_increment_and_decrement_counters() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+
+    assert_eq!(client.incr("counter".to_string(), 1).await.unwrap(), None);
+
+    client.set("counter".to_string(), "10", 100).await.unwrap();
+
+    assert_eq!(
+        client.incr("counter".to_string(), 5).await.unwrap(),
+        Some(15)
+    );
+    assert_eq!(
+        client.decr("counter".to_string(), 3).await.unwrap(),
+        Some(12)
+    );
+    assert_eq!(
+        client.decr("counter".to_string(), 100).await.unwrap(),
+        Some(0)
+    );
+    assert_eq!(
+        client.get("counter".to_string()).await.unwrap(),
+        Some(b"0".to_vec())
+    );
+
+    client
+        .set("counter".to_string(), u64::MAX.to_string(), 100)
+        .await
+        .unwrap();
+    assert_eq!(
+        client.incr("counter".to_string(), 2).await.unwrap(),
+        Some(1)
+    );
+}
+
+#[tokio::test]
+async fn it_should_refuse_to_increment_non_numeric_values() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+
+    client
+        .set("counter".to_string(), "hola", 100)
+        .await
+        .unwrap();
+
+    let error = client.incr("counter".to_string(), 1).await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "CLIENT_ERROR cannot increment or decrement non-numeric value"
+    );
+
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    stream
+        .write_all(b"incr counter abc\r\ndecr counter 1 noreply\r\nget counter\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "CLIENT_ERROR invalid numeric delta argument\r\nVALUE counter 0 4\r\nhola\r\nEND\r\n",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn it_should_store_with_cas_only_when_item_was_not_modified() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+
+    assert_eq!(client.gets("test".to_string()).await.unwrap(), None);
+    assert_eq!(
+        client
+            .cas("test".to_string(), "hola", 100, 1)
+            .await
+            .unwrap(),
+        CasResponse::NotFound
+    );
+
+    client.set("test".to_string(), "hola", 100).await.unwrap();
+    let (value, cas_unique) = client.gets("test".to_string()).await.unwrap().unwrap();
+    assert_eq!(value, b"hola".to_vec());
+
+    client.set("test".to_string(), "hello", 100).await.unwrap();
+    let (_, new_cas_unique) = client.gets("test".to_string()).await.unwrap().unwrap();
+    assert!(new_cas_unique > cas_unique);
+
+    assert_eq!(
+        client
+            .cas("test".to_string(), "lost update", 100, cas_unique)
+            .await
+            .unwrap(),
+        CasResponse::Exists
+    );
+    assert_eq!(
+        client
+            .cas("test".to_string(), "bonjour", 100, new_cas_unique)
+            .await
+            .unwrap(),
+        CasResponse::Stored
+    );
+    assert_eq!(
+        client.get("test".to_string()).await.unwrap(),
+        Some(b"bonjour".to_vec())
+    );
+}
+
+#[tokio::test]
+async fn it_should_change_cas_unique_on_counter_updates() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+
+    client.set("counter".to_string(), "1", 100).await.unwrap();
+    let (_, cas_unique) = client.gets("counter".to_string()).await.unwrap().unwrap();
+
+    client.incr("counter".to_string(), 1).await.unwrap();
+
+    assert_eq!(
+        client
+            .cas("counter".to_string(), "10", 100, cas_unique)
+            .await
+            .unwrap(),
+        CasResponse::Exists
+    );
+}
+
+#[tokio::test]
+async fn it_should_return_every_value_found_for_several_keys() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set a 0 100 1\r\n1\r\nset b 0 100 2\r\n22\r\nget a missing b a\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "STORED\r\nSTORED\r\nVALUE a 0 1\r\n1\r\nVALUE b 0 2\r\n22\r\nVALUE a 0 1\r\n1\r\nEND\r\n",
+    )
+    .await;
+
+    stream.write_all(b"gets missing\r\n").await.unwrap();
+    read_exactly(&mut stream, "END\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_get_multiple_keys_across_servers() {
+    let servers = [
+        TestServer::start(),
+        TestServer::start(),
+        TestServer::start(),
+    ];
+    let mut client =
+        memcached_client::Client::connect(servers.iter().map(|server| server.address()).collect())
+            .await
+            .unwrap();
+
+    let mut expected = HashMap::new();
+    for index in 0..40 {
+        let key = format!("key{}", index);
+        let value = format!("value{}", index).into_bytes();
+        client.set(key.clone(), &value, 100).await.unwrap();
+        expected.insert(key, value);
+    }
+
+    let mut keys: Vec<String> = expected.keys().cloned().collect();
+    keys.push("missing".to_string());
+    let values = client.get_multi(keys).await.unwrap();
+
+    assert_eq!(values, expected);
+}
+
+#[tokio::test]
+async fn it_should_read_every_reply_of_a_multi_get_that_fails() {
+    let servers = [TestServer::start(), TestServer::start()];
+    let mut client =
+        memcached_client::Client::connect(servers.iter().map(|server| server.address()).collect())
+            .await
+            .unwrap();
+
+    let keys: Vec<String> = (0..20).map(|index| format!("key{}", index)).collect();
+    for key in &keys {
+        client.set(key.clone(), "hola", 100).await.unwrap();
+    }
+
+    // refused by its server, whichever reply is read first
+    let mut with_invalid_key = keys.clone();
+    with_invalid_key.push("a".repeat(300));
+    assert!(client.get_multi(with_invalid_key).await.is_err());
+
+    for key in keys {
+        assert_eq!(client.get(key).await.unwrap(), Some(b"hola".to_vec()));
+    }
+}
+
+#[tokio::test]
+async fn it_should_touch_the_value() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+
+    assert!(!client.touch("test".to_string(), 100).await.unwrap());
+
+    client.set("test".to_string(), "hola", 100).await.unwrap();
+    assert!(client.touch("test".to_string(), 200).await.unwrap());
+    assert_eq!(
+        client.get("test".to_string()).await.unwrap(),
+        Some(b"hola".to_vec())
+    );
+
+    assert!(client.touch("test".to_string(), -1).await.unwrap());
+    assert_eq!(client.get("test".to_string()).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn it_should_get_and_touch_the_value() {
+    let server = TestServer::start();
+    let mut client = memcached_client::Client::connect(vec![server.address()])
+        .await
+        .unwrap();
+
+    assert_eq!(client.gat("test".to_string(), 100).await.unwrap(), None);
+
+    client.set("test".to_string(), "hola", 100).await.unwrap();
+    let (_, cas_unique) = client.gets("test".to_string()).await.unwrap().unwrap();
+
+    assert_eq!(
+        client.gats("test".to_string(), 200).await.unwrap(),
+        Some((b"hola".to_vec(), cas_unique))
+    );
+    assert_eq!(
+        client.gat("test".to_string(), -1).await.unwrap(),
+        Some(b"hola".to_vec())
+    );
+    assert_eq!(client.get("test".to_string()).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn it_should_touch_without_reply_and_get_and_touch_several_keys() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set a 0 100 1\r\n1\r\ntouch a 200 noreply\r\ngat 300 a missing a\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "STORED\r\nVALUE a 0 1\r\n1\r\nVALUE a 0 1\r\n1\r\nEND\r\n",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn it_should_follow_memcached_expiration_time_semantics() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let commands = format!(
+        "set never 0 0 1\r\n1\r\nset past 0 {} 1\r\n2\r\nset future 0 {} 1\r\n3\r\nset gone 0 -1 1\r\n4\r\n",
+        now - 100,
+        now + 100
+    );
+    stream.write_all(commands.as_bytes()).await.unwrap();
+    read_exactly(&mut stream, "STORED\r\nSTORED\r\nSTORED\r\nSTORED\r\n").await;
+
+    stream
+        .write_all(b"get never past future gone\r\nreplace past 0 0 1\r\n5\r\nappend gone 0 0 1\r\n6\r\nmg never t\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "VALUE never 0 1\r\n1\r\nVALUE future 0 1\r\n3\r\nEND\r\nNOT_STORED\r\nNOT_STORED\r\nHD t-1\r\n",
+    )
+    .await;
+}
+
+/**
+ * Server refusing flush_all and finding no key, to see how clients cope with
+ * a server failing a request the others serve.
+ */
+async fn start_refusing_flush_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (rd, mut wr) = stream.into_split();
+                let mut lines = BufReader::new(rd).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let reply = match line.starts_with("flush_all") {
+                        true => "SERVER_ERROR flush_all disabled\r\n",
+                        false => "END\r\n",
+                    };
+                    if wr.write_all(reply.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    address
+}
+
+#[tokio::test]
+async fn it_should_read_every_reply_of_a_flush_that_fails() {
+    let server = TestServer::start();
+    let refusing = start_refusing_flush_server().await;
+    let mut client = memcached_client::Client::connect(vec![refusing, server.address()])
+        .await
+        .unwrap();
+
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let keys: Vec<String> = (0..20).map(|index| format!("key{}", index)).collect();
+    for key in &keys {
+        stream
+            .write_all(format!("set {} 0 100 4\r\nhola\r\n", key).as_bytes())
+            .await
+            .unwrap();
+        read_exactly(&mut stream, "STORED\r\n").await;
+    }
+
+    // the refusing server is replied to first, the other one still flushes
+    assert!(client.flush_all(0).await.is_err());
+    for key in keys {
+        assert_eq!(client.get(key).await.unwrap(), None);
+    }
+}
+
+#[tokio::test]
+async fn it_should_flush_every_server() {
+    let servers = [TestServer::start(), TestServer::start()];
+    let mut client =
+        memcached_client::Client::connect(servers.iter().map(|server| server.address()).collect())
+            .await
+            .unwrap();
+
+    let keys: Vec<String> = (0..20).map(|index| format!("key{}", index)).collect();
+    for key in &keys {
+        client.set(key.clone(), "hola", 100).await.unwrap();
+    }
+
+    client.flush_all(0).await.unwrap();
+    assert!(client.get_multi(keys).await.unwrap().is_empty());
+
+    client.set("test".to_string(), "hola", 100).await.unwrap();
+    assert_eq!(
+        client.get("test".to_string()).await.unwrap(),
+        Some(b"hola".to_vec())
+    );
+}
+
+#[tokio::test]
+async fn it_should_flush_after_the_given_delay() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set a 0 100 1\r\n1\r\nflush_all 1\r\nset b 0 100 1\r\n2\r\nget a b\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "STORED\r\nOK\r\nSTORED\r\nVALUE a 0 1\r\n1\r\nVALUE b 0 1\r\n2\r\nEND\r\n",
+    )
+    .await;
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    stream
+        .write_all(b"set c 0 100 1\r\n3\r\nflush_all 0 noreply\r\nget a b c\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nEND\r\n").await;
+
+    stream
+        .write_all(b"set d 0 100 1\r\n4\r\nget a b c d\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nVALUE d 0 1\r\n4\r\nEND\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_take_flush_delays_beyond_30_days_as_unix_times() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let commands = format!("set a 0 0 1\r\n1\r\nflush_all {}\r\nget a\r\n", now + 100);
+    stream.write_all(commands.as_bytes()).await.unwrap();
+    read_exactly(&mut stream, "STORED\r\nOK\r\nVALUE a 0 1\r\n1\r\nEND\r\n").await;
+
+    // already past, so right away
+    let commands = format!("flush_all {}\r\nget a\r\n", now - 100);
+    stream.write_all(commands.as_bytes()).await.unwrap();
+    read_exactly(&mut stream, "OK\r\nEND\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_report_and_reset_stats() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set a 0 100 1\r\n1\r\nset b 0 -1 1\r\n2\r\nget a b c\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "STORED\r\nSTORED\r\nVALUE a 0 1\r\n1\r\nEND\r\n",
+    )
+    .await;
+
+    let stats = read_stats(&mut stream, "stats").await;
+    assert_eq!(stats["cmd_set"], "2");
+    assert_eq!(stats["total_items"], "2");
+    assert_eq!(stats["cmd_get"], "3");
+    assert_eq!(stats["get_hits"], "1");
+    assert_eq!(stats["get_misses"], "2");
+    assert_eq!(stats["get_expired"], "1");
+    assert_eq!(stats["expired_unfetched"], "1");
+    assert_eq!(stats["curr_items"], "1");
+    // key and value plus the item header
+    assert_eq!(stats["bytes"], "58");
+    assert_eq!(stats["limit_maxbytes"], (64 * 1024 * 1024).to_string());
+    assert_ne!(stats["curr_connections"], "0");
+    assert!(stats.contains_key("uptime"));
+    assert!(stats.contains_key("pid"));
+
+    stream.write_all(b"stats reset\r\n").await.unwrap();
+    read_exactly(&mut stream, "RESET\r\n").await;
+
+    let stats = read_stats(&mut stream, "stats").await;
+    assert_eq!(stats["cmd_get"], "0");
+    assert_eq!(stats["get_hits"], "0");
+    assert_eq!(stats["curr_items"], "1");
+}
+
+#[tokio::test]
+async fn it_should_report_settings_items_and_conns_stats() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let local_address = stream.local_addr().unwrap();
+
+    let settings = read_stats(&mut stream, "stats settings").await;
+    assert_eq!(
+        server.address(),
+        format!("127.0.0.1:{}", settings["tcpport"])
+    );
+
+    assert!(read_stats(&mut stream, "stats items").await.is_empty());
+    stream.write_all(b"set a 0 100 1\r\n1\r\n").await.unwrap();
+    read_exactly(&mut stream, "STORED\r\n").await;
+    let items = read_stats(&mut stream, "stats items").await;
+    assert_eq!(items["items:1:number"], "1");
+
+    let conns = read_stats(&mut stream, "stats conns").await;
+    let addr = format!("tcp:{}", local_address);
+    assert!(conns.values().any(|value| *value == addr), "{:?}", conns);
+
+    stream.write_all(b"stats nothing\r\n").await.unwrap();
+    read_exactly(&mut stream, "ERROR\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_store_items_in_the_slab_class_of_their_size() {
+    // chunks of 72, 144, 288 and 1024 bytes
+    let server = TestServer::start_with(&["-n", "16", "-f", "2", "-I", "1k"]);
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(
+            format!(
+                "set a 0 0 1\r\n1\r\nset b 0 0 100\r\n{}\r\n",
+                "b".repeat(100)
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nSTORED\r\n").await;
+
+    let slabs = read_stats(&mut stream, "stats slabs").await;
+    assert_eq!(slabs["1:chunk_size"], "72");
+    assert_eq!(slabs["1:chunks_per_page"], "14");
+    assert_eq!(slabs["1:total_pages"], "1");
+    assert_eq!(slabs["1:used_chunks"], "1");
+    assert_eq!(slabs["1:free_chunks"], "13");
+    assert_eq!(slabs["3:chunk_size"], "288");
+    assert!(!slabs.contains_key("2:chunk_size"));
+    assert_eq!(slabs["active_slabs"], "2");
+    assert_eq!(slabs["total_malloced"], "2048");
+
+    let items = read_stats(&mut stream, "stats items").await;
+    assert_eq!(items["items:1:number"], "1");
+    assert_eq!(items["items:3:number"], "1");
+
+    stream.write_all(b"me b\r\n").await.unwrap();
+    let expected = "ME b exp=-1 la=0 cas=2 fetch=no cls=3 size=101\r\n";
+    read_exactly(&mut stream, expected).await;
+
+    let settings = read_stats(&mut stream, "stats settings").await;
+    assert_eq!(settings["growth_factor"], "2");
+    assert_eq!(settings["chunk_size"], "16");
+}
+
+#[tokio::test]
+async fn it_should_report_the_lru_segments_of_every_class() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set short 0 30 1\r\n1\r\nset long 0 0 1\r\n1\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nSTORED\r\n").await;
+
+    let items = read_stats(&mut stream, "stats items").await;
+    assert_eq!(items["items:1:number"], "2");
+    assert_eq!(items["items:1:number_temp"], "1");
+    // the maintainer may have moved it to cold already
+    let segments = ["hot", "warm", "cold"]
+        .map(|segment| items[&format!("items:1:number_{}", segment)].as_str());
+    assert!(
+        matches!(segments, ["1", "0", "0"] | ["0", "0", "1"]),
+        "{:?}",
+        segments
+    );
+
+    let settings = read_stats(&mut stream, "stats settings").await;
+    assert_eq!(settings["eviction_policy"], "lru");
+    assert_eq!(settings["lru_segmented"], "yes");
+    assert_eq!(settings["temporary_ttl"], "61");
+    assert!(read_stats(&mut stream, "stats")
+        .await
+        .contains_key("lru_maintainer_juggles"));
+}
+
+#[tokio::test]
+async fn it_should_move_pages_to_the_class_running_out_of_them() {
+    let server = TestServer::start_with(&["-m", "1", "-I", "512k", "-o", "shards=1"]);
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let value = "a".repeat(300 * 1024);
+
+    // both pages go to the class of the big values
+    for key in ["big1", "big2"] {
+        stream
+            .write_all(format!("set {} 0 0 {}\r\n{}\r\n", key, value.len(), value).as_bytes())
+            .await
+            .unwrap();
+        read_exactly(&mut stream, "STORED\r\n").await;
+    }
+    stream
+        .write_all(b"delete big1\r\nset small 0 0 2\r\nhi\r\nget small\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "DELETED\r\nSTORED\r\nVALUE small 0 2\r\nhi\r\nEND\r\n",
+    )
+    .await;
+    assert_eq!(read_stats(&mut stream, "stats").await["slabs_moved"], "1");
+}
+
+#[tokio::test]
+async fn it_should_answer_version_and_verbosity() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream.write_all(b"version\r\n").await.unwrap();
+    read_exactly(
+        &mut stream,
+        &format!("VERSION {}\r\n", env!("CARGO_PKG_VERSION")),
+    )
+    .await;
+
+    stream
+        .write_all(b"verbosity 2\r\nverbosity 1 noreply\r\nverbosity\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "OK\r\nERROR\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_close_the_connection_on_quit() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set a 0 100 1\r\n1\r\nquit\r\nget a\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\n").await;
+
+    let mut buf = vec![];
+    let read = timeout(RESPONSE_TIMEOUT, stream.read_to_end(&mut buf))
+        .await
+        .expect("timed out waiting for the connection to close")
+        .unwrap();
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn it_should_set_and_get_with_meta_commands() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(
+            b"ms a 2 T100 F5\r\nhi\r\nmg a v f k Oxy\r\nmg missing v q\r\nmg missing v\r\nmn\r\n",
+        )
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "HD\r\nVA 2 f5 ka Oxy\r\nhi\r\nEN\r\nMN\r\n").await;
+
+    stream
+        .write_all(b"mg YQ== b k v h\r\nms a 2 q\r\nho\r\nms a 2 ME\r\nhu\r\nmg a v\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "VA 2 h1 kYQ== b\r\nhi\r\nNS\r\nVA 2\r\nho\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_update_counters_and_delete_with_meta_commands() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"ma b\r\nma b N0 J10 v\r\nma b v D5\r\nma b MD v\r\nma b q\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "NF\r\nVA 2\r\n10\r\nVA 2\r\n15\r\nVA 2\r\n14\r\n",
+    )
+    .await;
+
+    stream
+        .write_all(b"md b C1\r\nmd b q\r\nmd b q\r\nmd b\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "EX\r\nNF\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_compare_cas_in_every_meta_set_mode() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(
+            b"ms a 2\r\nhi\r\nms a 2 MA C999999\r\nho\r\nms a 2 MP C999999\r\nho\r\n\
+              ms a 2 ME C999999\r\nho\r\nms missing 2 MA C1\r\nho\r\nma a C999999\r\nmg a v\r\n",
+        )
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "HD\r\nEX\r\nEX\r\nEX\r\nNS\r\nEX\r\nVA 2\r\nhi\r\n",
+    )
+    .await;
+
+    stream.write_all(b"mg a c\r\n").await.unwrap();
+    let mut buf = [0; 64];
+    let read = timeout(RESPONSE_TIMEOUT, stream.read(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    let reply = std::str::from_utf8(&buf[..read]).unwrap();
+    let cas_unique = reply.trim_end().strip_prefix("HD c").unwrap().to_owned();
+    stream
+        .write_all(format!("ms a 2 MA C{}\r\nho\r\nmg a v\r\n", cas_unique).as_bytes())
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "HD\r\nVA 4\r\nhiho\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_serve_invalidated_items_until_recached() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"ms c 1\r\nx\r\nmd c I T30\r\nmg c v\r\nmg c v\r\n")
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "HD\r\nHD\r\nVA 1 X W\r\nx\r\nVA 1 X Z\r\nx\r\n",
+    )
+    .await;
+
+    stream
+        .write_all(b"ms c 1\r\ny\r\nmg c v\r\nmg missing N30 s\r\nmg missing s\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "HD\r\nVA 1\r\ny\r\nHD s0 W\r\nHD s0 Z\r\n").await;
+}
+
+#[tokio::test]
+async fn it_should_reject_invalid_keys() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let long_key = "a".repeat(251);
+
+    stream
+        .write_all(format!("set {} 0 0 4\r\nhola\r\nget {}\r\n", long_key, long_key).as_bytes())
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "CLIENT_ERROR bad command line format\r\nCLIENT_ERROR bad command line format\r\n",
+    )
+    .await;
+
+    stream.write_all(b"get a\x01b\r\nget a\r\n").await.unwrap();
+    read_exactly(
+        &mut stream,
+        "CLIENT_ERROR bad command line format\r\nEND\r\n",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn it_should_swallow_items_bigger_than_the_max_item_size() {
+    let server = TestServer::start_with(&["-I", "1k"]);
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let value = "a".repeat(2000);
+
+    stream
+        .write_all(format!("set big 0 0 2000\r\n{}\r\nget big\r\n", value).as_bytes())
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "SERVER_ERROR object too large for cache\r\nEND\r\n",
+    )
+    .await;
+
+    let value = "a".repeat(900);
+    stream
+        .write_all(
+            format!(
+                "set big 0 0 900\r\n{}\r\nappend big 0 0 100\r\n{}\r\n",
+                value,
+                &value[..100]
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    read_exactly(
+        &mut stream,
+        "STORED\r\nSERVER_ERROR out of memory storing object\r\n",
+    )
+    .await;
+
+    let stats = read_stats(&mut stream, "stats settings").await;
+    assert_eq!(stats["item_size_max"], "1024");
+}
+
+#[tokio::test]
+async fn it_should_evict_items_once_the_memory_limit_is_reached() {
+    let server = TestServer::start_with(&["-m", "2"]);
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let value = "a".repeat(900 * 1024);
+
+    for key in ["a", "b", "c"] {
+        stream
+            .write_all(format!("set {} 0 0 {}\r\n{}\r\n", key, value.len(), value).as_bytes())
+            .await
+            .unwrap();
+        read_exactly(&mut stream, "STORED\r\n").await;
+    }
+
+    stream.write_all(b"mg a\r\nmg b\r\nmg c\r\n").await.unwrap();
+    read_exactly(&mut stream, "EN\r\nHD\r\nHD\r\n").await;
+
+    let stats = read_stats(&mut stream, "stats").await;
+    assert_eq!(stats["evictions"], "1");
+    assert_eq!(stats["curr_items"], "2");
+    assert_eq!(stats["limit_maxbytes"], (2 * 1024 * 1024).to_string());
+}
+
+#[tokio::test]
+async fn it_should_evict_with_the_configured_policy() {
+    let server = TestServer::start_with(&["-m", "2", "-o", "eviction_policy=lfu"]);
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let value = "a".repeat(900 * 1024);
+    let set = |key| format!("set {} 0 0 {}\r\n{}\r\n", key, value.len(), value);
+
+    stream.write_all(set("a").as_bytes()).await.unwrap();
+    read_exactly(&mut stream, "STORED\r\n").await;
+    stream.write_all(set("b").as_bytes()).await.unwrap();
+    read_exactly(&mut stream, "STORED\r\n").await;
+    // "a" is used more often, although less recently
+    stream.write_all(b"mg a\r\nmg a\r\nmg b\r\n").await.unwrap();
+    read_exactly(&mut stream, "HD\r\nHD\r\nHD\r\n").await;
+    stream.write_all(set("c").as_bytes()).await.unwrap();
+    read_exactly(&mut stream, "STORED\r\n").await;
+
+    stream.write_all(b"mg a\r\nmg b\r\nmg c\r\n").await.unwrap();
+    read_exactly(&mut stream, "HD\r\nEN\r\nHD\r\n").await;
+
+    let settings = read_stats(&mut stream, "stats settings").await;
+    assert_eq!(settings["eviction_policy"], "lfu");
+    assert_eq!(settings["lru_segmented"], "no");
+}
+
+#[tokio::test]
+async fn it_should_serve_keys_from_every_shard() {
+    let server = TestServer::start_with(&["-o", "shards=4"]);
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    let keys: Vec<String> = (0..20).map(|key| format!("key{}", key)).collect();
+    for key in &keys {
+        stream
+            .write_all(format!("set {} 0 0 1\r\n1\r\n", key).as_bytes())
+            .await
+            .unwrap();
+        read_exactly(&mut stream, "STORED\r\n").await;
+    }
+    stream
+        .write_all(format!("get {}\r\n", keys.join(" ")).as_bytes())
+        .await
+        .unwrap();
+    let values: String = keys
+        .iter()
+        .map(|key| format!("VALUE {} 0 1\r\n1\r\n", key))
+        .collect();
+    read_exactly(&mut stream, &format!("{}END\r\n", values)).await;
+
+    let stats = read_stats(&mut stream, "stats").await;
+    assert_eq!(stats["curr_items"], "20");
+    let settings = read_stats(&mut stream, "stats settings").await;
+    assert_eq!(settings["shards"], "4");
+}
+
+#[tokio::test]
+async fn it_should_reclaim_expired_items_in_the_background() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    stream
+        .write_all(b"set a 0 0 1\r\n1\r\nset b 0 1 1\r\n2\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nSTORED\r\n").await;
+
+    let mut stats = read_stats(&mut stream, "stats").await;
+    for _ in 0..50 {
+        if stats["crawler_reclaimed"] == "1" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stats = read_stats(&mut stream, "stats").await;
+    }
+    assert_eq!(stats["crawler_reclaimed"], "1");
+    assert_eq!(stats["reclaimed"], "1");
+    assert_eq!(stats["expired_unfetched"], "1");
+    assert_eq!(stats["curr_items"], "1");
+    assert_ne!(stats["lru_crawler_starts"], "0");
+    assert_ne!(stats["crawler_items_checked"], "0");
+
+    let items = read_stats(&mut stream, "stats items").await;
+    assert_eq!(items["items:1:reclaimed"], "1");
+}
+
+#[tokio::test]
+async fn it_should_dump_the_metadata_of_every_item() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    let big = "a".repeat(200);
+    stream
+        .write_all(format!("set a%b 0 0 1\r\n1\r\nset big 0 100 200\r\n{}\r\n", big).as_bytes())
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "STORED\r\nSTORED\r\n").await;
+    stream.write_all(b"gets a%b\r\n").await.unwrap();
+    read_exactly(&mut stream, "VALUE a%b 0 1 1\r\n1\r\nEND\r\n").await;
+
+    let dump = read_until_end(&mut stream, "lru_crawler metadump all").await;
+    let mut lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.pop(), Some("END"));
+    lines.sort();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("key=a%25b exp=-1 la="), "{}", lines[0]);
+    assert!(
+        lines[0].ends_with(" cas=1 fetch=yes size=60"),
+        "{}",
+        lines[0]
+    );
+    assert!(lines[1].starts_with("key=big exp="), "{}", lines[1]);
+    assert!(lines[1].ends_with(" fetch=no size=259"), "{}", lines[1]);
+
+    let dump = read_until_end(&mut stream, "lru_crawler metadump 1").await;
+    assert_eq!(dump.lines().count(), 2);
+    assert!(dump.starts_with("key=a%25b "));
+
+    stream
+        .write_all(b"lru_crawler metadump 99\r\n")
+        .await
+        .unwrap();
+    read_exactly(&mut stream, "CLIENT_ERROR bad command line format\r\n").await;
+}
+
+struct BinaryResponse {
+    opcode: u8,
+    status: u16,
+    opaque: u32,
+    cas: u64,
+    extras: Vec<u8>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+fn binary_request(opcode: u8, extras: &[u8], key: &str, value: &str) -> Vec<u8> {
+    let body_length = extras.len() + key.len() + value.len();
+    let mut request = vec![0x80, opcode];
+    request.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    request.push(extras.len() as u8);
+    request.extend_from_slice(&[0, 0, 0]);
+    request.extend_from_slice(&(body_length as u32).to_be_bytes());
+    request.extend_from_slice(&u32::from(opcode).to_be_bytes());
+    request.extend_from_slice(&0u64.to_be_bytes());
+    request.extend_from_slice(extras);
+    request.extend_from_slice(key.as_bytes());
+    request.extend_from_slice(value.as_bytes());
+    request
+}
+
+async fn read_binary_response(stream: &mut TcpStream) -> BinaryResponse {
+    let mut header = [0; 24];
+    timeout(RESPONSE_TIMEOUT, stream.read_exact(&mut header))
+        .await
+        .expect("timed out waiting for the response")
+        .unwrap();
+    assert_eq!(header[0], 0x81);
+
+    let key_length = u16::from_be_bytes([header[2], header[3]]) as usize;
+    let extras_length = header[4] as usize;
+    let body_length = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+    let mut body = vec![0; body_length];
+    stream.read_exact(&mut body).await.unwrap();
+    let value = body.split_off(extras_length + key_length);
+    let key = body.split_off(extras_length);
+
+    BinaryResponse {
+        opcode: header[1],
+        status: u16::from_be_bytes([header[6], header[7]]),
+        opaque: u32::from_be_bytes(header[12..16].try_into().unwrap()),
+        cas: u64::from_be_bytes(header[16..24].try_into().unwrap()),
+        extras: body,
+        key,
+        value,
+    }
+}
+
+#[tokio::test]
+async fn it_should_share_items_between_binary_and_text_clients() {
+    let server = TestServer::start();
+    let mut binary = TcpStream::connect(server.address()).await.unwrap();
+    let mut text = TcpStream::connect(server.address()).await.unwrap();
+
+    let mut extras = 3u32.to_be_bytes().to_vec();
+    extras.extend_from_slice(&100u32.to_be_bytes());
+    binary
+        .write_all(&binary_request(0x01, &extras, "test", "hola"))
+        .await
+        .unwrap();
+    let response = read_binary_response(&mut binary).await;
+    assert_eq!((response.opcode, response.status), (0x01, 0));
+    assert_eq!(response.opaque, 0x01);
+    assert_ne!(response.cas, 0);
+
+    text.write_all(b"gets test\r\n").await.unwrap();
+    read_exactly(
+        &mut text,
+        &format!("VALUE test 3 4 {}\r\nhola\r\nEND\r\n", response.cas),
+    )
+    .await;
+
+    binary
+        .write_all(&binary_request(0x0c, &[], "test", ""))
+        .await
+        .unwrap();
+    let response = read_binary_response(&mut binary).await;
+    assert_eq!(response.status, 0);
+    assert_eq!(response.extras, 3u32.to_be_bytes());
+    assert_eq!(response.key, b"test");
+    assert_eq!(response.value, b"hola");
+
+    binary
+        .write_all(&binary_request(0x00, &[], "missing", ""))
+        .await
+        .unwrap();
+    let response = read_binary_response(&mut binary).await;
+    assert_eq!(response.status, 0x0001);
+}
+
+#[tokio::test]
+async fn it_should_refuse_binary_values_bigger_than_the_max_item_size() {
+    let server = TestServer::start_with(&["-I", "1k"]);
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    let mut extras = 0u32.to_be_bytes().to_vec();
+    extras.extend_from_slice(&100u32.to_be_bytes());
+    let mut requests = binary_request(0x01, &extras, "big", &"a".repeat(2000));
+    requests.extend(binary_request(0x01, &extras, "small", "hola"));
+    stream.write_all(&requests).await.unwrap();
+
+    let response = read_binary_response(&mut stream).await;
+    assert_eq!((response.opcode, response.status), (0x01, 0x0003));
+    assert_eq!(response.value, b"Too large.");
+    let response = read_binary_response(&mut stream).await;
+    assert_eq!((response.opcode, response.status), (0x01, 0));
+}
+
+#[tokio::test]
+async fn it_should_only_answer_quiet_binary_requests_when_needed() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    let mut extras = 0u32.to_be_bytes().to_vec();
+    extras.extend_from_slice(&100u32.to_be_bytes());
+    let mut requests = binary_request(0x11, &extras, "a", "1");
+    requests.extend(binary_request(0x11, &extras, "b", "2"));
+    requests.extend(binary_request(0x09, &[], "missing", ""));
+    requests.extend(binary_request(0x0d, &[], "b", ""));
+    requests.extend(binary_request(0x0a, &[], "", ""));
+    stream.write_all(&requests).await.unwrap();
+
+    let response = read_binary_response(&mut stream).await;
+    assert_eq!((response.opcode, response.status), (0x0d, 0));
+    assert_eq!(
+        (response.key, response.value),
+        (b"b".to_vec(), b"2".to_vec())
+    );
+
+    let response = read_binary_response(&mut stream).await;
+    assert_eq!((response.opcode, response.status), (0x0a, 0));
+}
+
+#[tokio::test]
+async fn it_should_answer_binary_counters_version_stats_and_quit() {
+    let server = TestServer::start();
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+
+    let mut extras = 2u64.to_be_bytes().to_vec();
+    extras.extend_from_slice(&10u64.to_be_bytes());
+    extras.extend_from_slice(&0u32.to_be_bytes());
+    for expected in [10u64, 12] {
+        stream
+            .write_all(&binary_request(0x05, &extras, "counter", ""))
+            .await
+            .unwrap();
+        let response = read_binary_response(&mut stream).await;
+        assert_eq!(response.status, 0);
+        assert_eq!(response.value, expected.to_be_bytes());
+    }
+
+    stream
+        .write_all(&binary_request(0x0b, &[], "", ""))
+        .await
+        .unwrap();
+    let response = read_binary_response(&mut stream).await;
+    assert_eq!(response.value, env!("CARGO_PKG_VERSION").as_bytes());
+
+    stream
+        .write_all(&binary_request(0x10, &[], "", ""))
+        .await
+        .unwrap();
+    let mut stats = HashMap::new();
+    loop {
+        let response = read_binary_response(&mut stream).await;
+        if response.key.is_empty() {
+            break;
+        }
+        stats.insert(
+            String::from_utf8(response.key).unwrap(),
+            String::from_utf8(response.value).unwrap(),
+        );
+    }
+    assert_eq!(stats["incr_misses"], "1");
+    assert_eq!(stats["incr_hits"], "1");
+
+    stream
+        .write_all(&binary_request(0x07, &[], "", ""))
+        .await
+        .unwrap();
+    let response = read_binary_response(&mut stream).await;
+    assert_eq!((response.opcode, response.status), (0x07, 0));
+    let mut buf = vec![];
+    let read = timeout(RESPONSE_TIMEOUT, stream.read_to_end(&mut buf))
+        .await
+        .expect("timed out waiting for the connection to close")
+        .unwrap();
+    assert_eq!(read, 0);
+}
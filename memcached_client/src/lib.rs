@@ -1,18 +1,26 @@
 mod protocol_parser;
 
+pub use crate::protocol_parser::CasResponse;
 use crate::protocol_parser::*;
-use bytes::BytesMut;
-use std::collections::hash_map::DefaultHasher;
+use std::collections::{hash_map::DefaultHasher, HashMap};
 use std::error::Error;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::io::{self, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
 use tokio::net::{TcpStream, ToSocketAddrs};
 use tokio::sync::Mutex;
 
 type HashId = u64;
 type MutexWriteHalfTcpStream = Arc<Mutex<WriteHalf<TcpStream>>>;
-type MutexReadHalfTcpStream = Arc<Mutex<ReadHalf<TcpStream>>>;
+type MutexReadHalfTcpStream = Arc<Mutex<BufReader<ReadHalf<TcpStream>>>>;
+
+fn storage_request(command: &str, key: &str, value: &[u8], exptime: isize) -> Vec<u8> {
+    let mut request = format!("{} {} 0 {} {}\r\n", command, key, exptime, value.len()).into_bytes();
+    request.extend_from_slice(value);
+    request.extend_from_slice(b"\r\n");
+
+    request
+}
 
 fn calculate_hash<T: Hash>(t: &T) -> HashId {
     let mut s = DefaultHasher::new();
@@ -45,115 +53,301 @@ impl Client {
     pub async fn set(
         &mut self,
         key: String,
-        value: String,
+        value: impl AsRef<[u8]>,
         exptime: isize,
     ) -> Result<&Client, Box<dyn Error>> {
         let (wr, rd) = self.get_write_and_read_conn(&key);
 
-        tokio::spawn(async move {
-            wr.lock()
-                .await
-                .write_all(
-                    format!("set {} 0 {} {}--{}--", key, exptime, value.len(), value).as_bytes(),
-                )
-                .await?;
+        Client::send(wr, storage_request("set", &key, value.as_ref(), exptime));
 
-            // Sometimes, the rust type inferencer needs
-            // a little help
-            Ok::<_, io::Error>(())
-        });
+        read_line(&mut *rd.lock().await).await?;
 
-        let mut buf = BytesMut::with_capacity(1024);
+        Ok(self)
+    }
 
-        rd.lock().await.read_buf(&mut buf).await?;
+    /**
+     * Returns the value stored for `key` byte for byte, or `None` if the key
+     * is not in the cache.
+     */
+    pub async fn get(&mut self, key: String) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let (wr, rd) = self.get_write_and_read_conn(&key);
 
-        println!("GOT {:?}", String::from_utf8(buf.to_vec()));
+        Client::send(wr, format!("get {}\r\n", key).into_bytes());
 
-        Ok(self)
+        let value = read_value(&mut *rd.lock().await).await?;
+
+        Ok(value.map(|value| value.data))
+    }
+
+    /**
+     * Retrieves several keys at once. Keys are grouped by the server they
+     * belong to and a single request is sent to each of those servers, all of
+     * them in flight at the same time. Keys not in the cache are left out of
+     * the returned map.
+     */
+    pub async fn get_multi(
+        &mut self,
+        keys: Vec<String>,
+    ) -> Result<HashMap<String, Vec<u8>>, Box<dyn Error>> {
+        let mut keys_by_connection: HashMap<usize, Vec<String>> = HashMap::new();
+        for key in keys {
+            keys_by_connection
+                .entry(self.select_connection_index(&key))
+                .or_default()
+                .push(key);
+        }
+
+        for (index, keys) in &keys_by_connection {
+            let request = format!("get {}\r\n", keys.join(" "));
+            Client::send(self.connections[*index].wr.clone(), request.into_bytes());
+        }
+
+        // every reply is read even after an error, so none is left behind
+        // for the next request sent on its connection to take as its own
+        let mut result = HashMap::new();
+        let mut first_error = None;
+        for index in keys_by_connection.keys() {
+            let rd = self.connections[*index].rd.clone();
+            let values = read_values(&mut *rd.lock().await).await;
+            match values {
+                Ok(values) => {
+                    for value in values {
+                        result.insert(value.key, value.data);
+                    }
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(result),
+        }
     }
 
-    pub async fn get(&mut self, key: String) -> Result<String, Box<dyn Error>> {
+    /**
+     * Returns the value stored for `key` along with the CAS unique to pass to
+     * `cas`, or `None` if the key is not in the cache.
+     */
+    pub async fn gets(&mut self, key: String) -> Result<Option<(Vec<u8>, u64)>, Box<dyn Error>> {
         let (wr, rd) = self.get_write_and_read_conn(&key);
 
-        tokio::spawn(async move {
-            wr.lock()
-                .await
-                .write_all(format!("get {}", key).as_bytes())
-                .await?;
+        Client::send(wr, format!("gets {}\r\n", key).into_bytes());
 
-            // Sometimes, the rust type inferencer needs
-            // a little help
-            Ok::<_, io::Error>(())
-        });
+        let value = read_value(&mut *rd.lock().await).await?;
+
+        value_with_cas_unique(value)
+    }
+
+    /**
+     * Returns the value stored for `key`, like `get`, and sets its expiration
+     * time to `exptime` in the same request.
+     */
+    pub async fn gat(
+        &mut self,
+        key: String,
+        exptime: isize,
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let (wr, rd) = self.get_write_and_read_conn(&key);
+
+        Client::send(wr, format!("gat {} {}\r\n", exptime, key).into_bytes());
+
+        let value = read_value(&mut *rd.lock().await).await?;
+
+        Ok(value.map(|value| value.data))
+    }
 
-        let mut buf = BytesMut::with_capacity(1024);
+    /**
+     * Same as `gat`, also returning the CAS unique to pass to `cas`.
+     */
+    pub async fn gats(
+        &mut self,
+        key: String,
+        exptime: isize,
+    ) -> Result<Option<(Vec<u8>, u64)>, Box<dyn Error>> {
+        let (wr, rd) = self.get_write_and_read_conn(&key);
+
+        Client::send(wr, format!("gats {} {}\r\n", exptime, key).into_bytes());
 
-        rd.lock().await.read_buf(&mut buf).await?;
+        let value = read_value(&mut *rd.lock().await).await?;
 
-        let data = String::from_utf8(buf.to_vec()).unwrap();
+        value_with_cas_unique(value)
+    }
 
-        println!("GOT {:?}", data);
+    /**
+     * Stores `value` only if the item was not modified since `gets` returned
+     * `cas_unique`.
+     */
+    pub async fn cas(
+        &mut self,
+        key: String,
+        value: impl AsRef<[u8]>,
+        exptime: isize,
+        cas_unique: u64,
+    ) -> Result<CasResponse, Box<dyn Error>> {
+        let (wr, rd) = self.get_write_and_read_conn(&key);
 
-        parse_response(data)
+        let value = value.as_ref();
+        let mut request = format!(
+            "cas {} 0 {} {} {}\r\n",
+            key,
+            exptime,
+            value.len(),
+            cas_unique
+        )
+        .into_bytes();
+        request.extend_from_slice(value);
+        request.extend_from_slice(b"\r\n");
+        Client::send(wr, request);
+
+        let response = read_line(&mut *rd.lock().await).await?;
+
+        parse_cas_response(&response)
     }
 
-    // Add documentation about the bool response
+    /**
+     * Returns `false` when the value was not stored because the key already
+     * exists.
+     */
     pub async fn add(
         &mut self,
         key: String,
-        value: String,
+        value: impl AsRef<[u8]>,
         exptime: isize,
     ) -> Result<bool, Box<dyn Error>> {
         let (wr, rd) = self.get_write_and_read_conn(&key);
 
-        tokio::spawn(async move {
-            wr.lock()
-                .await
-                .write_all(
-                    format!("add {} 0 {} {}--{}--", key, exptime, value.len(), value).as_bytes(),
-                )
-                .await?;
+        Client::send(wr, storage_request("add", &key, value.as_ref(), exptime));
 
-            // Sometimes, the rust type inferencer needs
-            // a little help
-            Ok::<_, io::Error>(())
-        });
+        let response = read_line(&mut *rd.lock().await).await?;
+
+        Ok(response != "NOT_STORED")
+    }
+
+    /**
+     * Returns `false` when there was nothing to delete for `key`.
+     */
+    pub async fn delete(&mut self, key: String) -> Result<bool, Box<dyn Error>> {
+        let (wr, rd) = self.get_write_and_read_conn(&key);
+
+        Client::send(wr, format!("delete {}\r\n", key).into_bytes());
+
+        let response = read_line(&mut *rd.lock().await).await?;
+
+        Ok(response == "DELETED")
+    }
+
+    /**
+     * Sets a new expiration time for `key` without fetching its value.
+     * Returns `false` when the key is not in the cache.
+     */
+    pub async fn touch(&mut self, key: String, exptime: isize) -> Result<bool, Box<dyn Error>> {
+        let (wr, rd) = self.get_write_and_read_conn(&key);
 
-        let mut buf = BytesMut::with_capacity(1024);
+        Client::send(wr, format!("touch {} {}\r\n", key, exptime).into_bytes());
 
-        rd.lock().await.read_buf(&mut buf).await?;
+        let response = read_line(&mut *rd.lock().await).await?;
 
-        let response = String::from_utf8(buf.to_vec());
+        parse_touch_response(&response)
+    }
 
-        println!("GOT {:?}", response);
+    /**
+     * Invalidates every item on every server, after `delay` seconds or right
+     * away when `delay` is 0.
+     */
+    pub async fn flush_all(&mut self, delay: u32) -> Result<(), Box<dyn Error>> {
+        for connection in &self.connections {
+            Client::send(
+                connection.wr.clone(),
+                format!("flush_all {}\r\n", delay).into_bytes(),
+            );
+        }
 
-        match response {
-            Ok(resp) => {
-                if resp.trim() == "NOT_STORED" {
-                    Ok(false)
-                } else {
-                    Ok(true)
+        // every reply is read even after an error, as get_multi does it
+        let mut first_error = None;
+        for connection in &self.connections {
+            let response = read_line(&mut *connection.rd.lock().await).await;
+            match response {
+                Ok(response) if response == "OK" => {}
+                Ok(response) => {
+                    first_error.get_or_insert(unexpected_response(&response));
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
                 }
             }
-            Err(err) => Err(err.into()),
+        }
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
         }
     }
 
+    /**
+     * Increments the counter stored for `key`, returning its new value or
+     * `None` if the key is not in the cache.
+     */
+    pub async fn incr(&mut self, key: String, delta: u64) -> Result<Option<u64>, Box<dyn Error>> {
+        self.update_counter("incr", key, delta).await
+    }
+
+    /**
+     * Decrements the counter stored for `key`, returning its new value or
+     * `None` if the key is not in the cache. Counters never go below zero.
+     */
+    pub async fn decr(&mut self, key: String, delta: u64) -> Result<Option<u64>, Box<dyn Error>> {
+        self.update_counter("decr", key, delta).await
+    }
+
+    async fn update_counter(
+        &mut self,
+        command: &str,
+        key: String,
+        delta: u64,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        let (wr, rd) = self.get_write_and_read_conn(&key);
+
+        Client::send(
+            wr,
+            format!("{} {} {}\r\n", command, key, delta).into_bytes(),
+        );
+
+        let response = read_line(&mut *rd.lock().await).await?;
+
+        parse_counter_response(&response)
+    }
+
+    fn send(wr: MutexWriteHalfTcpStream, request: Vec<u8>) {
+        tokio::spawn(async move {
+            wr.lock().await.write_all(&request).await?;
+
+            // Sometimes, the rust type inferencer needs
+            // a little help
+            Ok::<_, io::Error>(())
+        });
+    }
+
     async fn create_connection<A: ToSocketAddrs>(addr: A) -> Result<Connection, Box<dyn Error>> {
         let stream = TcpStream::connect(addr).await?;
 
         let (rd, wr) = io::split(stream);
 
         Ok(Connection {
-            rd: Arc::new(Mutex::new(rd)),
+            rd: Arc::new(Mutex::new(BufReader::new(rd))),
             wr: Arc::new(Mutex::new(wr)),
         })
     }
 
-    fn select_connection(&self, key: &str) -> &Connection {
+    fn select_connection_index(&self, key: &str) -> usize {
         let hash = calculate_hash(&key.to_string());
-        let index = hash as usize % self.connections.len();
-        return &self.connections[index];
+        hash as usize % self.connections.len()
+    }
+
+    fn select_connection(&self, key: &str) -> &Connection {
+        &self.connections[self.select_connection_index(key)]
     }
 
     fn get_write_and_read_conn(
@@ -164,6 +358,6 @@ impl Client {
         let wr = connection.wr.clone();
         let rd = connection.rd.clone();
 
-        return (wr, rd);
+        (wr, rd)
     }
 }
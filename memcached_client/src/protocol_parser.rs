@@ -1,10 +1,14 @@
 use std::{
     error::{self, Error},
-    fmt,
+    fmt, io,
 };
 
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
 type ResponseResult<T> = std::result::Result<T, Box<dyn Error>>;
 
+const TERMINATOR: &[u8] = b"\r\n";
+
 #[derive(Debug, Clone)]
 struct InvalidResponseError;
 
@@ -16,56 +20,297 @@ impl fmt::Display for InvalidResponseError {
 
 impl error::Error for InvalidResponseError {}
 
-pub fn parse_response(response: String) -> ResponseResult<String> {
-    let list: Vec<&str> = response.trim().split("\r\n").collect();
-    if list.len() < 2 {
+/**
+ * ERROR, CLIENT_ERROR or SERVER_ERROR line sent by the server.
+ */
+#[derive(Debug, Clone)]
+struct ErrorResponse(String);
+
+impl fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for ErrorResponse {}
+
+pub fn unexpected_response(line: &str) -> Box<dyn Error> {
+    if line == "ERROR" || line.starts_with("CLIENT_ERROR ") || line.starts_with("SERVER_ERROR ") {
+        return ErrorResponse(line.to_owned()).into();
+    }
+
+    InvalidResponseError.into()
+}
+
+fn invalid_response(error: io::Error) -> Box<dyn Error> {
+    if error.kind() == io::ErrorKind::UnexpectedEof {
+        return InvalidResponseError.into();
+    }
+
+    error.into()
+}
+
+/**
+ * Reads a response line, without its terminator.
+ */
+pub async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> ResponseResult<String> {
+    let mut line = vec![];
+    reader
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(invalid_response)?;
+
+    if !line.ends_with(TERMINATOR) {
         return Err(InvalidResponseError.into());
     }
+    line.truncate(line.len() - TERMINATOR.len());
+
+    String::from_utf8(line).map_err(|_| InvalidResponseError.into())
+}
+
+/**
+ * Item returned by a retrieval command. `cas_unique` is only sent in
+ * response to gets.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub key: String,
+    pub flags: u16,
+    pub data: Vec<u8>,
+    pub cas_unique: Option<u64>,
+}
+
+/**
+ * Reads the response to a single key retrieval, returning the data block
+ * byte for byte, or `None` when the key was not found.
+ */
+pub async fn read_value<R: AsyncBufRead + Unpin>(reader: &mut R) -> ResponseResult<Option<Value>> {
+    let mut values = read_values(reader).await?;
+    if values.len() > 1 {
+        return Err(InvalidResponseError.into());
+    }
+
+    Ok(values.pop())
+}
+
+/**
+ * Splits the value returned by gets or gats into its data block and CAS
+ * unique.
+ */
+pub fn value_with_cas_unique(value: Option<Value>) -> ResponseResult<Option<(Vec<u8>, u64)>> {
+    match value {
+        None => Ok(None),
+        Some(Value {
+            data,
+            cas_unique: Some(cas_unique),
+            ..
+        }) => Ok(Some((data, cas_unique))),
+        Some(_) => Err("Response is missing the CAS unique".into()),
+    }
+}
+
+/**
+ * Reads every VALUE block of a retrieval response up to its END line.
+ */
+pub async fn read_values<R: AsyncBufRead + Unpin>(reader: &mut R) -> ResponseResult<Vec<Value>> {
+    let mut values = vec![];
+
+    loop {
+        let line = read_line(reader).await?;
+        if line == "END" {
+            return Ok(values);
+        }
+
+        let tokens: Vec<&str> = line.split(' ').collect();
+        if (tokens.len() != 4 && tokens.len() != 5) || tokens[0] != "VALUE" {
+            return Err(unexpected_response(&line));
+        }
+        let flags: u16 = tokens[2].parse().map_err(|_| InvalidResponseError)?;
+        let size: usize = tokens[3].parse().map_err(|_| InvalidResponseError)?;
+        let cas_unique = match tokens.get(4) {
+            None => None,
+            Some(token) => Some(token.parse().map_err(|_| InvalidResponseError)?),
+        };
+
+        let mut data = vec![0; size + TERMINATOR.len()];
+        reader
+            .read_exact(&mut data)
+            .await
+            .map_err(invalid_response)?;
+        if !data.ends_with(TERMINATOR) {
+            return Err(InvalidResponseError.into());
+        }
+        data.truncate(size);
+
+        values.push(Value {
+            key: tokens[1].to_owned(),
+            flags,
+            data,
+            cas_unique,
+        });
+    }
+}
+
+/**
+ * Parses the response to incr and decr: the new value of the counter, or
+ * `None` when the key was not found.
+ */
+pub fn parse_counter_response(line: &str) -> ResponseResult<Option<u64>> {
+    if line == "NOT_FOUND" {
+        return Ok(None);
+    }
 
-    Ok(String::from(list[1]))
+    match line.parse() {
+        Ok(counter) => Ok(Some(counter)),
+        Err(_) => Err(unexpected_response(line)),
+    }
+}
+
+/**
+ * Parses the response to touch: `false` when the key was not found.
+ */
+pub fn parse_touch_response(line: &str) -> ResponseResult<bool> {
+    match line {
+        "TOUCHED" => Ok(true),
+        "NOT_FOUND" => Ok(false),
+        _ => Err(unexpected_response(line)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CasResponse {
+    Stored,
+    /// The item was modified since it was fetched
+    Exists,
+    NotFound,
+}
+
+pub fn parse_cas_response(line: &str) -> ResponseResult<CasResponse> {
+    match line {
+        "STORED" => Ok(CasResponse::Stored),
+        "EXISTS" => Ok(CasResponse::Exists),
+        "NOT_FOUND" => Ok(CasResponse::NotFound),
+        _ => Err(unexpected_response(line)),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn should_return_second_part_after_first_separator() {
-        let data = "VALUE test 0 4\r\nhola\r\nEND\r\n".to_string();
+    #[tokio::test]
+    async fn should_return_second_part_after_first_separator() {
+        let mut data = "VALUE test 0 4\r\nhola\r\nEND\r\n".as_bytes();
 
-        let result = parse_response(data);
-        assert_eq!(result.unwrap(), "hola");
+        let result = read_value(&mut data).await;
+        assert_eq!(
+            result.unwrap().map(|value| value.data),
+            Some(b"hola".to_vec())
+        );
     }
 
-    #[test]
-    fn should_raise_error_if_response_does_not_follow_expected_pattern() {
-        let data = "VALUE test 0 4".to_string();
+    #[tokio::test]
+    async fn should_return_none_when_key_is_not_found() {
+        let mut data = "END\r\n".as_bytes();
+
+        let result = read_value(&mut data).await;
+        assert_eq!(result.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn should_return_binary_data_block_byte_for_byte() {
+        let mut data = b"VALUE test 0 7\r\n\xff\x00\r\nEND\r\nEND\r\n".as_slice();
+
+        let result = read_value(&mut data).await;
+        assert_eq!(
+            result.unwrap().map(|value| value.data),
+            Some(b"\xff\x00\r\nEND".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_cas_unique_when_present() {
+        let mut data = "VALUE test 3 4 42\r\nhola\r\nEND\r\n".as_bytes();
+
+        let result = read_value(&mut data).await;
+        assert_eq!(
+            result.unwrap(),
+            Some(Value {
+                key: "test".to_owned(),
+                flags: 3,
+                data: b"hola".to_vec(),
+                cas_unique: Some(42),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_every_value_up_to_end() {
+        let mut data = "VALUE a 0 1\r\n1\r\nVALUE b 0 2\r\n22\r\nEND\r\n".as_bytes();
 
-        let result = parse_response(data).unwrap_err();
+        let values = read_values(&mut data).await.unwrap();
+        let keys: Vec<&str> = values.iter().map(|value| value.key.as_str()).collect();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(values[1].data, b"22".to_vec());
+    }
+
+    #[tokio::test]
+    async fn should_raise_error_if_response_does_not_follow_expected_pattern() {
+        let mut data = "VALUE test 0 4".as_bytes();
+
+        let result = read_value(&mut data).await.unwrap_err();
         assert_eq!(result.to_string(), "Response is invalid");
     }
 
-    #[test]
-    fn should_raise_error_if_response_not_contain_data_expected() {
-        let data = "VALUE test 0 4\r\n".to_string();
+    #[tokio::test]
+    async fn should_raise_error_if_response_not_contain_data_expected() {
+        let mut data = "VALUE test 0 4\r\n".as_bytes();
+
+        let result = read_value(&mut data).await.unwrap_err();
+        assert_eq!(result.to_string(), "Response is invalid");
+    }
+
+    #[tokio::test]
+    async fn should_raise_error_if_separator_comes_first() {
+        let mut data = "\r\nVALUE test 0 4".as_bytes();
 
-        let result = parse_response(data).unwrap_err();
+        let result = read_value(&mut data).await.unwrap_err();
         assert_eq!(result.to_string(), "Response is invalid");
     }
 
-		#[test]
-    fn should_raise_error_if_separator_comes_first() {
-        let data = "\r\nVALUE test 0 4".to_string();
+    #[tokio::test]
+    async fn should_raise_error_if_separator_comes_first_and_in_last_position() {
+        let mut data = "\r\nVALUE test 0 4\r\n".as_bytes();
 
-        let result = parse_response(data).unwrap_err();
+        let result = read_value(&mut data).await.unwrap_err();
         assert_eq!(result.to_string(), "Response is invalid");
     }
 
-		#[test]
-    fn should_raise_error_if_separator_comes_first_and_in_last_position() {
-        let data = "\r\nVALUE test 0 4\r\n".to_string();
+    #[tokio::test]
+    async fn should_raise_error_if_data_block_is_bigger_than_announced() {
+        let mut data = "VALUE test 0 4\r\nhello\r\nEND\r\n".as_bytes();
 
-        let result = parse_response(data).unwrap_err();
+        let result = read_value(&mut data).await.unwrap_err();
+        assert_eq!(result.to_string(), "Response is invalid");
+    }
+
+    #[test]
+    fn should_parse_counter_response() {
+        assert_eq!(parse_counter_response("42").unwrap(), Some(42));
+        assert_eq!(parse_counter_response("NOT_FOUND").unwrap(), None);
+    }
+
+    #[test]
+    fn should_raise_server_error_when_counter_is_not_numeric() {
+        let line = "CLIENT_ERROR cannot increment or decrement non-numeric value";
+
+        let result = parse_counter_response(line).unwrap_err();
+        assert_eq!(result.to_string(), line);
+    }
+
+    #[test]
+    fn should_raise_error_if_counter_response_is_unexpected() {
+        let result = parse_counter_response("STORED").unwrap_err();
         assert_eq!(result.to_string(), "Response is invalid");
     }
 }